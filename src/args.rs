@@ -1,6 +1,9 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+
+use crate::duration::parse_duration;
 
 #[derive(Parser, Clone, Debug)]
 #[command(name = "friggen")]
@@ -18,15 +21,68 @@ pub struct Args {
     #[arg(short = 'w', long, env = "FRIGGEN_WORKING_DIR", value_name = "PATH")]
     pub working_dir: Option<PathBuf>,
 
+    /// The shell that runs command substitutions, and task scripts without a shebang.
+    #[arg(long, env = "FRIGGEN_SHELL", value_name = "SHELL")]
+    pub shell: Option<String>,
+
+    /// Load environment variables from a file, on top of the `.env` next to the friggenfile.
+    #[arg(long, value_name = "PATH")]
+    pub env_file: Vec<PathBuf>,
+
     /// Supply an environment variable to task scripts.
     #[arg(short = 'e', long, value_name = "NAME=VALUE")]
     pub env_var: Vec<String>,
 
+    /// List private tasks too, and allow running them directly.
+    #[arg(short = 'a', long)]
+    pub all: bool,
+
+    /// List available tasks, even if the friggenfile sets a default task.
+    #[arg(short = 'l', long)]
+    pub list: bool,
+
+    /// The order tasks are listed in.
+    #[arg(long, value_enum, default_value_t = ListOrder::Source)]
+    pub sort: ListOrder,
+
+    /// Stop tasks without a `@timeout` of their own if they run longer than this, like `30m`.
+    #[arg(long, env = "FRIGGEN_TIMEOUT", value_name = "DURATION", value_parser = parse_duration_arg)]
+    pub timeout: Option<Duration>,
+
+    /// Run tasks even if their outputs are up to date.
+    #[arg(long)]
+    pub force: bool,
+
+    /// With `cache clean`, only remove cache entries that haven't been used for this long,
+    /// like `7d`.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration_arg)]
+    pub older_than: Option<Duration>,
+
     /// Only print task output.
     #[arg(short = 'q', long)]
     pub quiet: bool,
 
-    /// Names of tasks to run. Run with no arguments to list available tasks and task help.
+    /// Names of tasks to run, each followed by any arguments for its parameters, like `deploy env=prod`.
+    /// Run with no arguments to run the default task, or to list available tasks and task help
+    /// if the friggenfile doesn't set one. `help <task>` shows the full documentation of a task,
+    /// and `cache stats` and `cache clean` manage the cache of `@cache` tasks.
     #[arg()]
     pub tasks: Vec<String>,
+
+    /// Arguments following `--` are passed to the script of the last task named, as `$1..$N`.
+    #[arg(last = true, value_name = "SCRIPT_ARGS")]
+    pub script_args: Vec<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ListOrder {
+    /// The order tasks are defined in.
+    #[default]
+    Source,
+    /// Alphabetical order.
+    Alpha,
+}
+
+fn parse_duration_arg(s: &str) -> Result<Duration, String> {
+    parse_duration(s).ok_or_else(|| format!("invalid duration: {}", s))
 }
@@ -1,20 +1,29 @@
 #[derive(Debug, Clone, PartialEq)]
 pub enum AstNode<'src> {
     Root(Vec<Self>),
+    Include(&'src str),
+    Module(AstModule<'src>),
+    Setting(AstSetting<'src>),
+    Interpreter(AstInterpreter<'src>),
+    Group(&'src str),
     TaskDef(AstTaskDef<'src>),
     TaskDocs(Vec<&'src str>),
+    TaskAttr(AstTaskAttr<'src>),
     TaskHeader(AstTaskHeader<'src>),
+    TaskParam(AstTaskParam<'src>),
     TaskScript(AstTaskScript<'src>),
     TaskDepList(Vec<Self>),
     TaskDep(AstTaskDep<'src>),
     VarAssignment(AstVarAssignment<'src>),
     VarValue(&'src str),
+    InterpolatedValue(&'src str),
+    EscapedValue(&'src str),
     CommandSubstitution(&'src str),
 }
 
 impl<'src> AstNode<'src> {
     #[inline]
-    pub fn as_task_header(&self) -> &AstTaskHeader {
+    pub fn as_task_header(&self) -> &AstTaskHeader<'src> {
         match self {
             Self::TaskHeader(h) => h,
             _ => panic!("expected task header"),
@@ -30,7 +39,15 @@ impl<'src> AstNode<'src> {
     }
 
     #[inline]
-    pub fn as_task_script(&self) -> &AstTaskScript {
+    pub fn as_task_attr(&self) -> &AstTaskAttr<'src> {
+        match self {
+            Self::TaskAttr(a) => a,
+            _ => panic!("expected task attribute"),
+        }
+    }
+
+    #[inline]
+    pub fn as_task_script(&self) -> &AstTaskScript<'src> {
         match self {
             Self::TaskScript(s) => s,
             _ => panic!("expected task script"),
@@ -38,27 +55,75 @@ impl<'src> AstNode<'src> {
     }
 
     #[inline]
-    pub fn as_task_dep(&self) -> &AstTaskDep {
+    pub fn as_task_dep(&self) -> &AstTaskDep<'src> {
         match self {
             Self::TaskDep(d) => d,
             _ => panic!("expected task dep"),
         }
     }
+
+    #[inline]
+    pub fn as_task_param(&self) -> &AstTaskParam<'src> {
+        match self {
+            Self::TaskParam(p) => p,
+            _ => panic!("expected task param"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstModule<'src> {
+    pub name: &'src str,
+    pub path: &'src str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstSetting<'src> {
+    pub name: &'src str,
+    pub value: &'src str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstInterpreter<'src> {
+    pub name: &'src str,
+    pub command: &'src str,
+    pub suffix: Option<&'src str>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstTaskDef<'src> {
     pub docs: Option<Box<AstNode<'src>>>,
+    pub attrs: Vec<AstNode<'src>>,
     pub header: Box<AstNode<'src>>,
     pub script: Box<AstNode<'src>>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstTaskAttr<'src> {
+    pub name: &'src str,
+    pub args: Vec<AstAttrArg<'src>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstAttrArg<'src> {
+    pub key: Option<&'src str>,
+    pub value: &'src str,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstTaskHeader<'src> {
     pub name: &'src str,
+    pub aliases: Vec<&'src str>,
+    pub params: Vec<AstNode<'src>>,
     pub deps: Vec<AstNode<'src>>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstTaskParam<'src> {
+    pub name: &'src str,
+    pub default: Option<&'src str>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstTaskDep<'src> {
     pub name: &'src str,
@@ -74,5 +139,6 @@ pub struct AstTaskScript<'src> {
 #[derive(Debug, Clone, PartialEq)]
 pub struct AstVarAssignment<'src> {
     pub name: &'src str,
+    pub conditional: bool,
     pub value: Box<AstNode<'src>>,
 }
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::Result;
+use crate::friggenfile::{ScriptMode, Task};
+use crate::interpreter::Interpreter;
+use crate::ioutil::{copy_recursive, disk_size, read_file};
+use crate::shell::SCRIPT_DIR;
+
+const STDOUT_FILE: &str = "stdout";
+const OUTPUTS_DIR: &str = "outputs";
+
+/// Outputs and printed output of `@cache` tasks, by a hash of everything that goes into running
+/// them. Each entry is a directory named by the hash, with the captured stdout of the task and
+/// a copy of its `@outputs`.
+pub struct TaskCache {
+    dir: PathBuf,
+}
+
+/// How much is in the cache.
+#[derive(Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub size: u64,
+    pub oldest: Option<SystemTime>,
+    pub newest: Option<SystemTime>,
+}
+
+impl TaskCache {
+    /// The cache next to the scripts friggen writes.
+    pub fn open() -> Self {
+        let script_dir = PathBuf::from(shellexpand::tilde(SCRIPT_DIR).to_string());
+        Self::new(script_dir.join("cache"))
+    }
+
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Hash the script of a task along with how and where it runs, its environment, arguments
+    /// and the contents of its `@sources`.
+    pub fn key(
+        &self,
+        task: &Task<'_>,
+        interpreter: &Interpreter,
+        working_dir: &Path,
+        env: &HashMap<&str, &str>,
+        script_args: &[&str],
+    ) -> Result<String> {
+        let mut hasher = KeyHasher::default();
+        hasher.field(task.name.as_bytes());
+        for component in &interpreter.command {
+            hasher.field(component.as_bytes());
+        }
+        hasher.field(interpreter.suffix.as_bytes());
+        hasher.field(interpreter.preamble.unwrap_or_default().as_bytes());
+        hasher.field(match task.attrs.mode {
+            ScriptMode::Script => b"script",
+            ScriptMode::Lines => b"lines",
+        });
+        for line in &task.script {
+            hasher.field(line.as_bytes());
+        }
+        let working_dir = relative_dir(working_dir, &task.module.dir);
+        hasher.field(working_dir.to_string_lossy().as_bytes());
+
+        let mut env: Vec<(&&str, &&str)> = env.iter().collect();
+        env.sort();
+        for (name, value) in env {
+            hasher.field(name.as_bytes());
+            hasher.field(value.as_bytes());
+        }
+        for arg in script_args {
+            hasher.field(arg.as_bytes());
+        }
+
+        for output in &task.attrs.outputs {
+            hasher.field(output.as_bytes());
+        }
+        for path in task.source_files()? {
+            let relative = path.strip_prefix(&task.module.dir).unwrap_or(&path);
+            hasher.field(relative.to_string_lossy().as_bytes());
+            hasher.field(&read_file(&path)?);
+        }
+
+        Ok(hasher.finish())
+    }
+
+    /// Put the outputs of a cached run back in place under `dir`, returning the stdout of the
+    /// run, or `None` if there's no entry for the key.
+    pub fn restore(&self, key: &str, dir: &Path, outputs: &[&str]) -> Result<Option<Vec<u8>>> {
+        check_outputs(outputs)?;
+        let entry = self.dir.join(key);
+        let stdout_path = entry.join(STDOUT_FILE);
+        if !stdout_path.is_file() {
+            return Ok(None);
+        }
+
+        let cached_outputs = entry.join(OUTPUTS_DIR);
+        for output in outputs {
+            let cached = cached_outputs.join(output);
+            if !cached.exists() {
+                continue;
+            }
+            let path = dir.join(output);
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            }
+            copy_recursive(&cached, &path)?;
+        }
+
+        // The stdout file doubles as the last time the entry was used
+        let stdout = read_file(&stdout_path)?;
+        File::options()
+            .write(true)
+            .open(&stdout_path)?
+            .set_modified(SystemTime::now())?;
+        Ok(Some(stdout))
+    }
+
+    /// Save the stdout of a successful run, along with the outputs it left under `dir`.
+    pub fn store(&self, key: &str, dir: &Path, outputs: &[&str], stdout: &[u8]) -> Result<()> {
+        check_outputs(outputs)?;
+        fs::create_dir_all(&self.dir)?;
+        // Fill in the entry to the side, so that a half written entry is never used
+        let tmp = tempfile::Builder::new()
+            .prefix("friggen-")
+            .tempdir_in(&self.dir)?;
+
+        let cached_outputs = tmp.path().join(OUTPUTS_DIR);
+        fs::create_dir_all(&cached_outputs)?;
+        for output in outputs {
+            let path = dir.join(output);
+            if path.exists() {
+                copy_recursive(&path, &cached_outputs.join(output))?;
+            } else {
+                log::warn!("output not found: {}", path.display());
+            }
+        }
+        fs::write(tmp.path().join(STDOUT_FILE), stdout)?;
+
+        let entry = self.dir.join(key);
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
+        }
+        fs::rename(tmp.keep(), entry)?;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = CacheStats::default();
+        for (path, used) in self.entries()? {
+            stats.entries += 1;
+            stats.size += disk_size(&path)?;
+            stats.oldest = Some(stats.oldest.map_or(used, |oldest| oldest.min(used)));
+            stats.newest = Some(stats.newest.map_or(used, |newest| newest.max(used)));
+        }
+        Ok(stats)
+    }
+
+    /// Remove entries that haven't been used for a while, or all of them. Returns the number of
+    /// entries removed.
+    pub fn clean(&self, older_than: Option<Duration>) -> Result<usize> {
+        let cutoff = older_than.and_then(|age| SystemTime::now().checked_sub(age));
+        let mut removed = 0;
+        for (path, used) in self.entries()? {
+            if cutoff.is_some_and(|cutoff| used >= cutoff) {
+                continue;
+            }
+            fs::remove_dir_all(path)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Every complete entry, with the last time it was used.
+    fn entries(&self) -> Result<Vec<(PathBuf, SystemTime)>> {
+        if !self.dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut entries = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if let Ok(meta) = fs::metadata(path.join(STDOUT_FILE)) {
+                entries.push((path, meta.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// A directory relative to the directory of a friggenfile, if it's inside it, so that a key doesn't
+/// depend on where the project is checked out.
+fn relative_dir(dir: &Path, module_dir: &Path) -> PathBuf {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let module_dir = fs::canonicalize(module_dir).unwrap_or_else(|_| module_dir.to_path_buf());
+    match dir.strip_prefix(&module_dir) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => dir,
+    }
+}
+
+/// Why an `@outputs` path can't be cached, if it can't. Outputs are copied in and out of a cache
+/// entry by their path, so the path has to stay inside the directory of the task.
+pub fn uncacheable_output(output: &str) -> Option<&'static str> {
+    let path = Path::new(output);
+    if path.is_absolute() {
+        Some("absolute paths can't be cached")
+    } else if path.components().any(|c| c == Component::ParentDir) {
+        Some("paths with .. can't be cached")
+    } else {
+        None
+    }
+}
+
+fn check_outputs(outputs: &[&str]) -> Result<()> {
+    for output in outputs {
+        if let Some(reason) = uncacheable_output(output) {
+            return Err(anyhow::Error::msg(format!("{}: {}", reason, output)).into());
+        }
+    }
+    Ok(())
+}
+
+/// A SHA-256 of a sequence of fields.
+#[derive(Default)]
+struct KeyHasher(Sha256);
+
+impl KeyHasher {
+    fn field(&mut self, bytes: &[u8]) {
+        // Length prefixes keep `ab` + `c` apart from `a` + `bc`
+        self.0.update((bytes.len() as u64).to_le_bytes());
+        self.0.update(bytes);
+    }
+
+    fn finish(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+/// How long ago a cache entry was used, like `3h ago`.
+pub fn format_age(time: SystemTime) -> String {
+    let secs = time.elapsed().unwrap_or_default().as_secs();
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Format a number of bytes for people, like `1.5 MiB`.
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", size, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::cache::{format_size, KeyHasher, TaskCache};
+    use crate::friggenfile::{
+        load_sources, Friggenfile, Module, ScriptMode, Task, TaskAttributes, TaskDocs,
+    };
+    use crate::interpreter::Interpreter;
+
+    fn hash(fields: &[&str]) -> String {
+        let mut hasher = KeyHasher::default();
+        for field in fields {
+            hasher.field(field.as_bytes());
+        }
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_key_hasher() {
+        assert_eq!(hash(&["ab", "c"]).len(), 64);
+        assert_eq!(hash(&["ab", "c"]), hash(&["ab", "c"]));
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+        assert_ne!(hash(&["ab"]), hash(&["ab", ""]));
+    }
+
+    fn task(module: &Module, mode: ScriptMode) -> Task<'_> {
+        Task {
+            name: Cow::Borrowed("build"),
+            aliases: vec![],
+            order: 0,
+            group: None,
+            module,
+            source: Path::new("friggenfile"),
+            attrs: TaskAttributes {
+                cache: true,
+                mode,
+                ..Default::default()
+            },
+            docs: TaskDocs::default(),
+            params: vec![],
+            deps: vec![],
+            hash_bang: None,
+            script: vec!["make\n"],
+        }
+    }
+
+    #[test]
+    fn test_key_working_dir_and_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        let cache = TaskCache::new(dir.path().join("cache"));
+        fs::write(dir.path().join("friggenfile"), "build:\n  make\n").unwrap();
+        let sources = load_sources(&dir.path().join("friggenfile")).unwrap();
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let items = ff.items();
+        let module = items[0].module;
+        let interpreter = Interpreter {
+            command: vec![Cow::Borrowed("bash")],
+            suffix: ".sh",
+            preamble: None,
+        };
+        let key = |mode, working_dir: &Path| {
+            cache
+                .key(
+                    &task(module, mode),
+                    &interpreter,
+                    working_dir,
+                    &HashMap::new(),
+                    &[],
+                )
+                .unwrap()
+        };
+
+        let a = key(ScriptMode::Script, &dir.path().join("a"));
+        assert_eq!(a, key(ScriptMode::Script, &dir.path().join("a")));
+        assert_ne!(a, key(ScriptMode::Script, &dir.path().join("b")));
+        assert_ne!(a, key(ScriptMode::Lines, &dir.path().join("a")));
+    }
+
+    #[test]
+    fn test_store_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TaskCache::new(dir.path().join("cache"));
+        let outputs = ["out/app", "missing"];
+
+        assert_eq!(cache.restore("abc", dir.path(), &outputs).unwrap(), None);
+
+        fs::create_dir(dir.path().join("out")).unwrap();
+        fs::write(dir.path().join("out/app"), "app").unwrap();
+        cache
+            .store("abc", dir.path(), &outputs, b"built\n")
+            .unwrap();
+        fs::remove_dir_all(dir.path().join("out")).unwrap();
+
+        assert_eq!(
+            cache
+                .restore("abc", dir.path(), &outputs)
+                .unwrap()
+                .as_deref(),
+            Some(&b"built\n"[..])
+        );
+        assert_eq!(fs::read(dir.path().join("out/app")).unwrap(), b"app");
+        assert!(!dir.path().join("missing").exists());
+    }
+
+    #[test]
+    fn test_store_outside_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TaskCache::new(dir.path().join("cache"));
+        let report = dir.path().join("report.txt");
+        fs::write(&report, "important data").unwrap();
+
+        let absolute = report.to_string_lossy();
+        assert!(cache
+            .store("abc", dir.path(), &[absolute.as_ref()], b"")
+            .is_err());
+        assert!(cache
+            .store("abc", &dir.path().join("sub"), &["../report.txt"], b"")
+            .is_err());
+        assert!(cache.restore("abc", dir.path(), &["/etc/passwd"]).is_err());
+        assert_eq!(fs::read(&report).unwrap(), b"important data");
+    }
+
+    #[test]
+    fn test_stats_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TaskCache::new(dir.path().join("cache"));
+        assert_eq!(cache.stats().unwrap().entries, 0);
+
+        cache.store("abc", dir.path(), &[], b"12345").unwrap();
+        cache.store("def", dir.path(), &[], b"678").unwrap();
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.size, 8);
+
+        assert_eq!(
+            cache
+                .clean(Some(std::time::Duration::from_secs(60)))
+                .unwrap(),
+            0
+        );
+        assert_eq!(cache.clean(None).unwrap(), 2);
+        assert_eq!(cache.stats().unwrap().entries, 0);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(12), "12 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
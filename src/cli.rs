@@ -3,12 +3,14 @@ use std::process::exit;
 
 use crate::args::Args;
 use crate::error::{FriggenError, Result};
-use crate::friggen::Friggen;
+use crate::friggen::{run_cache_command, Friggen, Options, CACHE_COMMAND};
 use crate::fs_context::resolve_fs_context;
 use crate::print::{OutputPrinter, PrintTheme};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const APP_NAME: &str = env!("CARGO_PKG_NAME");
+/// The exit code when a task times out, same as `timeout(1)`.
+pub const TIMED_OUT_EXIT_CODE: i32 = 124;
 
 pub struct Cli {
     args: Args,
@@ -33,18 +35,30 @@ impl Cli {
             .collect()
     }
 
-    fn create_friggen(&self) -> Result<Friggen> {
+    fn create_friggen(&self) -> Result<Friggen<'_>> {
         let fs_context = resolve_fs_context(
             self.args.friggenfile.as_deref(),
             self.args.working_dir.as_deref(),
         )?;
 
         let output_printer = OutputPrinter::new(PrintTheme::ThisFriggenKicksAss, self.args.quiet);
+        let options = Options {
+            all: self.args.all,
+            list: self.args.list,
+            sort: self.args.sort,
+            force: self.args.force,
+            timeout: self.args.timeout,
+            older_than: self.args.older_than,
+            env_files: self.args.env_file.clone(),
+            shell: self.args.shell.clone(),
+        };
 
         Ok(Friggen::new(
             fs_context,
             self.args.tasks.iter().map(|s| s.as_str()).collect(),
+            self.args.script_args.iter().map(|s| s.as_str()).collect(),
             self.parse_env_vars(),
+            options,
             output_printer,
         ))
     }
@@ -57,6 +71,17 @@ impl Cli {
 
         let friggen = match self.create_friggen() {
             Ok(f) => f,
+            // Without a friggenfile, there's no task named `cache` to get in the way
+            Err(FriggenError::FriggenfileNotFound)
+                if self.args.tasks.first().map(String::as_str) == Some(CACHE_COMMAND) =>
+            {
+                let args: Vec<&str> = self.args.tasks[1..].iter().map(|s| s.as_str()).collect();
+                if let Err(err) = run_cache_command(&args, self.args.older_than) {
+                    eprintln!("{}", err);
+                    exit(1);
+                }
+                return;
+            }
             Err(err) => {
                 eprintln!("{}", err);
                 exit(1);
@@ -69,6 +94,10 @@ impl Cli {
                     // Message already printed in task summary, but make sure we:
                     exit(exit_code)
                 }
+                FriggenError::TaskTimedOut { .. } => {
+                    // Same here
+                    exit(TIMED_OUT_EXIT_CODE)
+                }
                 _ => {
                     eprintln!("{}", err);
                     exit(1);
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 use crate::parser::Rule;
@@ -12,21 +14,80 @@ pub enum FriggenError {
     #[error("friggenfile syntax error:\n{0}")]
     FriggenfileSyntaxError(Box<pest::error::Error<Rule>>),
 
-    #[error("duplicate task definition: {0}")]
-    DuplicateTaskDefinition(String),
+    #[error("include not found: {include} ({referrer})")]
+    IncludeNotFound { include: String, referrer: String },
+
+    #[error("module friggenfile not found: {module} ({path})")]
+    ModuleNotFound { module: String, path: String },
+
+    #[error("cyclic include: {0:?}")]
+    CyclicInclude(Vec<String>),
+
+    #[error("unknown setting: {0}")]
+    UnknownSetting(String),
+
+    #[error("invalid setting: {setting}: {reason}")]
+    InvalidSetting { setting: String, reason: String },
+
+    #[error("duplicate task definition: {task} ({first}, {second})")]
+    DuplicateTaskDefinition {
+        task: String,
+        first: String,
+        second: String,
+    },
 
     #[error("task not found: {0}")]
     TaskNotFound(String),
 
+    #[error("duplicate task parameter: {task} {param}")]
+    DuplicateTaskParameter { task: String, param: String },
+
+    #[error("missing task argument: {task} {param}")]
+    MissingTaskArgument { task: String, param: String },
+
+    #[error("unexpected task argument: {task} {arg}")]
+    UnexpectedTaskArgument { task: String, arg: String },
+
+    #[error("option only works with {command}: {option}")]
+    UnexpectedOption { option: String, command: String },
+
+    #[error("invalid interpreter: {name}: {reason}")]
+    InvalidInterpreter { name: String, reason: String },
+
+    #[error("unknown task attribute: {task} @{attr}")]
+    UnknownTaskAttribute { task: String, attr: String },
+
+    #[error("invalid task attribute: {task} @{attr}: {reason}")]
+    InvalidTaskAttribute {
+        task: String,
+        attr: String,
+        reason: String,
+    },
+
+    #[error("task is private: {0} (use --all to run it anyway)")]
+    PrivateTask(String),
+
     #[error("invalid task reference: {referrer} » {referee}")]
     InvalidTaskReference { referrer: String, referee: String },
 
     #[error("cyclic task reference: {0:?}")]
     CyclicTaskReference(Vec<String>),
 
+    #[error("undefined variable: {referrer} » {name}")]
+    UndefinedVariable { referrer: String, name: String },
+
+    #[error("cyclic variable reference: {0:?}")]
+    CyclicVariableReference(Vec<String>),
+
+    #[error("task not confirmed: {0}")]
+    TaskNotConfirmed(String),
+
     #[error("task failed: {task} ({exit_code})")]
     TaskError { task: String, exit_code: i32 },
 
+    #[error("task timed out: {task} (after {timeout:?})")]
+    TaskTimedOut { task: String, timeout: Duration },
+
     #[error("io error: {source:?}")]
     Io {
         #[from]
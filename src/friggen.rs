@@ -1,37 +1,92 @@
 use std::borrow::Cow;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::time::SystemTime;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use std::{env, thread};
 
 use colored::Colorize;
 
+use crate::args::ListOrder;
 use crate::ast::AstNode;
+use crate::cache::{format_age, format_size, TaskCache};
 use crate::error::{FriggenError, Result};
-use crate::friggenfile::{Friggenfile, Task, TaskDep};
+use crate::friggenfile::{
+    load_sources, Friggenfile, Item, ScriptMode, Settings, Task, TaskAttributes, TaskDep, TaskDocs,
+    TaskParam,
+};
 use crate::fs_context::FsContext;
-use crate::ioutil::read_file;
+use crate::interpreter::{Interpreter, Interpreters};
+use crate::ioutil::{confirm, read_env_file};
 use crate::print::{OutputPrinter, PrintTheme};
-use crate::shell::{eval_shell_command, run_shell_script};
+use crate::shell::{run_shell_command, run_shell_script, split_commands};
+use crate::vars::resolve_vars;
+
+/// The env file that's loaded from next to the friggenfile, if there is one.
+const DOTENV_FILE: &str = ".env";
+
+/// `friggen help <task>` shows the full documentation of a task, unless there's a task named
+/// `help`.
+const HELP_COMMAND: &str = "help";
+
+/// `friggen cache stats` and `friggen cache clean` manage the cache of `@cache` tasks, unless
+/// there's a task named `cache`.
+pub const CACHE_COMMAND: &str = "cache";
+
+/// A task requested on the command line, along with the arguments given for its parameters.
+#[derive(Debug)]
+struct TaskInvocation<'a> {
+    name: &'a str,
+    args: HashMap<&'a str, &'a str>,
+}
+
+/// Command line options that change how tasks are listed and run.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub all: bool,
+    pub list: bool,
+    pub sort: ListOrder,
+    pub force: bool,
+    /// The timeout of tasks without a `@timeout`.
+    pub timeout: Option<Duration>,
+    /// With `cache clean`, only remove entries that haven't been used for this long.
+    pub older_than: Option<Duration>,
+    /// Env files from the command line, loaded after the ones the friggenfile names.
+    pub env_files: Vec<PathBuf>,
+    pub shell: Option<String>,
+}
+
+/// What tasks of the same module with the same `@env_file` files see of the environment.
+struct TaskEnv<'src> {
+    env_file_vars: HashMap<String, String>,
+    vars: HashMap<&'src str, Cow<'src, str>>,
+}
 
 pub struct Friggen<'a> {
     fs_context: FsContext,
     tasks: Vec<&'a str>,
+    script_args: Vec<&'a str>,
     env_vars: HashMap<&'a str, &'a str>,
+    options: Options,
     output_printer: OutputPrinter,
 }
 
 impl<'a> Friggen<'a> {
-    const DEFAULT_HASH_BANG: [&'static str; 2] = ["/usr/bin/env", "bash"];
-
     pub fn new(
         fs_context: FsContext,
         tasks: Vec<&'a str>,
+        script_args: Vec<&'a str>,
         env_vars: HashMap<&'a str, &'a str>,
+        options: Options,
         output_printer: OutputPrinter,
     ) -> Self {
         Self {
             fs_context,
             tasks,
+            script_args,
             env_vars,
+            options,
             output_printer,
         }
     }
@@ -39,32 +94,160 @@ impl<'a> Friggen<'a> {
     pub fn run(&self) -> Result<()> {
         let start_time = SystemTime::now();
 
-        let buf = read_file(&self.fs_context.friggenfile)?;
-        let buf = String::from_utf8_lossy(&buf);
-        let ff = Friggenfile::from(&buf)?;
-        log::debug!("ast: {:?}", ff.ast());
+        let sources = load_sources(&self.fs_context.friggenfile)?;
+        let ff = Friggenfile::from_sources(&sources)?;
+        let items = ff.items();
+        log::debug!("items: {:?}", items);
 
-        let mut tasks: HashMap<&str, Task<'_>> = HashMap::new();
-        build_task_map(ff.ast(), &mut tasks)?;
+        let mut tasks: HashMap<Cow<'_, str>, Task<'_>> = HashMap::new();
+        let mut aliases: HashMap<Cow<'_, str>, Cow<'_, str>> = HashMap::new();
+        build_task_map(&items, &mut tasks, &mut aliases)?;
         validate_tasks(&tasks)?;
 
-        let mut vars: HashMap<&str, Cow<'_, str>> = HashMap::new();
-        self.build_var_map(ff.ast(), &mut vars)?;
-        log::debug!("vars: {:?}", vars);
+        // Settings come from the root friggenfile, not from modules
+        let mut settings = Settings::from_ast(
+            items
+                .iter()
+                .filter(|item| item.module.namespace.is_none())
+                .map(|item| item.node),
+        )?;
+        if let Some(shell) = self.options.shell.as_deref() {
+            settings.shell = Some(shell);
+        }
+        log::debug!("settings: {:?}", settings);
+
+        let env_file_vars = self.load_env_files(&settings)?;
+
+        // Interpreters can be defined in any module
+        let interpreters = Interpreters::from_ast(items.iter().map(|item| item.node))?;
 
-        if self.tasks.is_empty() {
+        if let Some(cache_args) = command_args(CACHE_COMMAND, &self.tasks, &tasks, &aliases) {
+            return run_cache_command(cache_args, self.options.older_than);
+        }
+        if self.options.older_than.is_some() {
+            return Err(older_than_error());
+        }
+
+        if let Some(help_args) = command_args(HELP_COMMAND, &self.tasks, &tasks, &aliases) {
+            match help_args {
+                [] => self.print_docs(&tasks),
+                [task_name] => {
+                    let task_name = aliases
+                        .get(*task_name)
+                        .map(|name| name.as_ref())
+                        .unwrap_or(task_name);
+                    let task = tasks
+                        .get(task_name)
+                        .ok_or_else(|| FriggenError::TaskNotFound(task_name.to_string()))?;
+                    self.print_task_help(task);
+                }
+                [_, arg, ..] => {
+                    return Err(FriggenError::UnexpectedTaskArgument {
+                        task: HELP_COMMAND.to_string(),
+                        arg: arg.to_string(),
+                    })
+                }
+            }
+            return Ok(());
+        }
+
+        let task_args: Vec<&str> = if self.options.list {
+            vec![]
+        } else if self.tasks.is_empty() {
+            settings.default.into_iter().collect()
+        } else {
+            self.tasks.clone()
+        };
+        if task_args.is_empty() {
             self.print_docs(&tasks);
             return Ok(());
         }
 
-        let task_seq = build_task_sequence(&self.tasks, &tasks)?;
+        let invocations = parse_task_invocations(&task_args, &tasks, &aliases)?;
+        log::debug!("invocations: {:?}", invocations);
+
+        if !self.options.all {
+            if let Some(inv) = invocations.iter().find(|inv| tasks[inv.name].is_private()) {
+                return Err(FriggenError::PrivateTask(inv.name.to_string()));
+            }
+        }
+
+        let requested_tasks: Vec<&str> = invocations.iter().map(|inv| inv.name).collect();
+        let task_seq = build_task_sequence(&requested_tasks, &tasks)?;
         log::debug!("sequence: {:?}", task_seq);
 
+        let mut task_args: HashMap<&str, HashMap<&str, &str>> = HashMap::new();
+        for task_name in &task_seq {
+            if !task_args.contains_key(task_name) {
+                let task = tasks.get(*task_name).expect("task name exists");
+                let invocation = invocations.iter().find(|inv| inv.name == *task_name);
+                let args = resolve_task_args(task, invocation)?;
+                task_args.insert(task_name, args);
+            }
+        }
+
+        // Only the last task named on the command line receives the script arguments
+        let script_args_task = requested_tasks.last().copied();
+
+        // Variables are evaluated the first time a task that sees them runs, so that command
+        // substitutions don't run when they aren't needed. Each module only sees the variables
+        // assigned in its own friggenfile, and `?=` defaults see the `@env_file` files of the
+        // task, so they are evaluated for each set of env files too.
+        let mut task_envs: HashMap<(Option<&str>, &[&str]), TaskEnv<'_>> = HashMap::new();
+        let fs_env = self.fs_context.env_vars();
+        let mut substitution_env = self.env_vars.clone();
+        substitution_env.extend(fs_env.iter().map(|(k, v)| (*k, v.as_str())));
+
         let mut last_task: &str = "";
         let mut last_code: i32 = 0;
         for task_name in task_seq {
+            let task = &tasks[task_name];
+            let module = task.module;
+            let key = (module.namespace.as_deref(), task.attrs.env_files.as_slice());
+            let task_env = match task_envs.entry(key) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    let env_file_vars: HashMap<String, String> =
+                        load_task_env_files(task, &env_file_vars)?
+                            .into_iter()
+                            .collect();
+                    let nodes: Vec<&AstNode> = items
+                        .iter()
+                        .filter(|item| std::ptr::eq(item.module, module))
+                        .map(|item| item.node)
+                        .collect();
+                    let vars = resolve_vars(
+                        &nodes,
+                        settings.shell(),
+                        Some(module.working_dir(&self.fs_context.working_dir)),
+                        &substitution_env,
+                        &str_map(&env_file_vars),
+                    )?;
+                    log::debug!("vars: {:?}", vars);
+                    e.insert(TaskEnv {
+                        env_file_vars,
+                        vars,
+                    })
+                }
+            };
+
+            let script_args: &[&str] = if Some(task_name) == script_args_task {
+                &self.script_args
+            } else {
+                &[]
+            };
+
+            let interpreter = interpreters.for_task(task, settings.shell(), settings.strict())?;
+
             last_task = task_name;
-            last_code = self.run_task(task_name, &tasks, &vars)?;
+            last_code = self.run_task(
+                task,
+                &task_args[task_name],
+                script_args,
+                &str_map(&task_env.env_file_vars),
+                &task_env.vars,
+                &interpreter,
+            )?;
             if last_code != 0 {
                 break;
             }
@@ -84,9 +267,12 @@ impl<'a> Friggen<'a> {
         Ok(())
     }
 
-    fn print_docs(&self, tasks: &HashMap<&str, Task<'_>>) {
-        let mut tasks: Vec<&Task> = tasks.values().collect();
-        tasks.sort_by(|a, b| a.name.partial_cmp(b.name).unwrap());
+    fn print_docs(&self, tasks: &HashMap<Cow<'_, str>, Task<'_>>) {
+        let mut tasks: Vec<&Task> = tasks
+            .values()
+            .filter(|task| self.options.all || !task.is_private())
+            .collect();
+        sort_tasks(&mut tasks, self.options.sort);
 
         println!(
             "{}",
@@ -110,8 +296,26 @@ impl<'a> Friggen<'a> {
         );
         println!();
 
+        let mut namespace = None;
+        let mut group = None;
         for task in tasks {
-            self.output_printer.print_section_header(task.name);
+            let task_ns = task.module.namespace.as_deref();
+            if task_ns != namespace {
+                namespace = task_ns;
+                group = None;
+                println!();
+                println!("{}", format!("{}:", task_ns.unwrap()).yellow().bold());
+            }
+            if task.group != group {
+                group = task.group;
+                if let Some(group) = group {
+                    println!();
+                    println!("{}", format!("── {} ──", group).bright_purple().bold());
+                }
+            }
+
+            self.output_printer
+                .print_section_header(&task_signature(task));
 
             if !task.deps.is_empty() {
                 let color_deps: Vec<_> = task
@@ -126,34 +330,221 @@ impl<'a> Friggen<'a> {
                 ));
             }
 
-            if let Some(docs) = &task.docs {
-                for line in docs {
-                    self.output_printer.print_section_line(line.trim());
-                }
+            if !task.docs.summary.is_empty() {
+                self.output_printer.print_section_line(&task.docs.summary());
             }
 
             self.output_printer.print_section_footer();
         }
     }
 
+    fn print_task_help(&self, task: &Task<'_>) {
+        let docs = &task.docs;
+        self.output_printer
+            .print_section_header(&task_signature(task));
+
+        for line in &docs.summary {
+            self.output_printer.print_section_line(line);
+        }
+        if !docs.description.is_empty() {
+            self.output_printer.print_section_line("");
+            for line in &docs.description {
+                self.output_printer.print_section_line(line);
+            }
+        }
+
+        if !task.deps.is_empty() {
+            self.output_printer.print_section_line("");
+            let deps: Vec<&str> = task.deps.iter().map(|dep| dep.name.as_ref()).collect();
+            self.output_printer.print_section_line(&format!(
+                "{} {}",
+                "depends on:".yellow().bold(),
+                deps.join(" » ").blue().bold()
+            ));
+        }
+
+        // Tags are grouped by name, in the order each tag first appears
+        let mut tag_names: Vec<&str> = vec![];
+        for tag in &docs.tags {
+            if !tag_names.contains(&tag.name) {
+                tag_names.push(tag.name);
+            }
+        }
+        for name in tag_names {
+            let title = match name {
+                "example" => "examples",
+                "env" => "environment",
+                name => name,
+            };
+            self.output_printer.print_section_line("");
+            self.output_printer
+                .print_section_line(&format!("{}:", title).yellow().bold().to_string());
+            for tag in docs.tags.iter().filter(|tag| tag.name == name) {
+                self.output_printer
+                    .print_section_line(&format!("  {}", tag.text));
+            }
+        }
+
+        self.output_printer.print_section_footer();
+    }
+
+    /// Read the `.env` next to the friggenfile, then the env files named by the friggenfile and
+    /// on the command line. Later files override earlier ones, and none of them override the
+    /// environment friggen runs in.
+    fn load_env_files(&self, settings: &Settings) -> Result<Vec<(String, String)>> {
+        let mut vars = vec![];
+        let dotenv = self.fs_context.root_dir.join(DOTENV_FILE);
+        if dotenv.is_file() {
+            vars.extend(read_env_file(&dotenv)?);
+        }
+        for env_file in &settings.env_files {
+            vars.extend(read_env_file(&self.fs_context.root_dir.join(env_file))?);
+        }
+        for env_file in &self.options.env_files {
+            vars.extend(read_env_file(env_file)?);
+        }
+        retain_unexported(&mut vars);
+        Ok(vars)
+    }
+
+    /// Run a task. Its environment is made of, from the lowest priority to the highest:
+    ///
+    /// 1. `.env`, `set env_file`, `--env-file` and then `@env_file` files, in `env_file_vars`
+    /// 2. the environment friggen runs in, which the script inherits
+    /// 3. friggenfile variables
+    /// 4. `-e NAME=VALUE`
+    /// 5. task arguments
     fn run_task(
         &self,
-        task_name: &str,
-        tasks: &HashMap<&str, Task<'_>>,
+        task: &Task<'_>,
+        args: &HashMap<&str, &str>,
+        script_args: &[&str],
+        env_file_vars: &HashMap<&str, &str>,
         vars: &HashMap<&str, Cow<'_, str>>,
+        interpreter: &Interpreter,
     ) -> Result<i32> {
         let start = SystemTime::now();
 
-        let task = tasks.get(task_name).expect("task name exists");
-        self.output_printer
-            .print_header(&format!("» start: {}", task_name));
+        let task_name = task.name.as_ref();
+        if !self.options.force && task.is_up_to_date()? {
+            self.output_printer
+                .with_theme(PrintTheme::NothingToFriggenDo)
+                .print_header(&format!("≡ up to date: {}", task_name));
+            return Ok(0);
+        }
+
+        let mut env: HashMap<&str, &str> = env_file_vars.clone();
+        env.extend(vars.iter().map(|(k, v)| (*k, v.as_ref())));
+        env.extend(self.env_vars.iter());
+        env.extend(args.iter());
+
+        let working_dir = task.working_dir(&self.fs_context.working_dir);
+        let cache = TaskCache::open();
+        let cache_key = if task.attrs.cache {
+            Some(cache.key(task, interpreter, &working_dir, &env, script_args)?)
+        } else {
+            None
+        };
+        if let Some(key) = cache_key.as_deref().filter(|_| !self.options.force) {
+            if let Some(stdout) = cache.restore(key, &task.module.dir, &task.attrs.outputs)? {
+                self.output_printer
+                    .with_theme(PrintTheme::NothingToFriggenDo)
+                    .print_header(&format!("♻ cached: {}", task_name));
+                io::stdout().write_all(&stdout)?;
+                return Ok(0);
+            }
+        }
+
+        if task.attrs.confirm && !confirm(&format!("Run task {}?", task_name))? {
+            return Err(FriggenError::TaskNotConfirmed(task_name.to_string()));
+        }
+
+        let timeout = task.attrs.timeout.or(self.options.timeout);
+        // Left out of the cache key, so that it doesn't matter where friggen runs from
+        let fs_env = self.fs_context.env_vars();
+        let attempts = task.attrs.retry.as_ref().map_or(1, |retry| retry.attempts);
+        let mut stdout = cache_key.as_ref().map(|_| vec![]);
+        let mut attempt = 1;
+        let code = loop {
+            if attempt == 1 {
+                self.output_printer
+                    .print_header(&format!("» start: {}", task_name));
+            } else {
+                self.output_printer.print_header(&format!(
+                    "» start: {} (attempt {}/{})",
+                    task_name, attempt, attempts
+                ));
+            }
 
-        let default_hash_bang = Vec::from(Self::DEFAULT_HASH_BANG);
-        let hash_bang = task.hash_bang.as_ref().unwrap_or(&default_hash_bang);
+            let attempt_str = attempt.to_string();
+            let mut env = env.clone();
+            env.extend(fs_env.iter().map(|(k, v)| (*k, v.as_str())));
+            env.insert("FRIGGEN_ATTEMPT", &attempt_str);
+            if let Some(stdout) = &mut stdout {
+                stdout.clear();
+            }
+
+            let code = match task.attrs.mode {
+                ScriptMode::Script => run_shell_script(
+                    interpreter,
+                    &task.script,
+                    script_args,
+                    &working_dir,
+                    &env,
+                    timeout,
+                    stdout.as_mut(),
+                )?,
+                ScriptMode::Lines => self.run_task_lines(
+                    task,
+                    interpreter,
+                    script_args,
+                    &env,
+                    timeout,
+                    stdout.as_mut(),
+                )?,
+            };
 
-        let code = run_shell_script(hash_bang, &task.script, &self.env_vars, vars)?;
+            match (code, &task.attrs.retry) {
+                (Some(code), Some(retry)) if retry.should_retry(attempt, code) => {
+                    let delay = retry.delay(attempt);
+                    self.output_printer
+                        .with_theme(PrintTheme::ThisFriggenSucks)
+                        .print_timed_header(
+                            &format!(
+                                "↻ failed: {} ({}), retrying in {:?}",
+                                task_name, code, delay
+                            ),
+                            start,
+                        );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                _ => break code,
+            }
+        };
+        let Some(code) = code else {
+            let timeout = timeout.expect("only tasks with a timeout time out");
+            self.output_printer
+                .with_theme(PrintTheme::ThisFriggenSucks)
+                .print_timed_header(
+                    &format!("⧗ timed out: {} (after {:?})", task_name, timeout),
+                    start,
+                );
+            return Err(FriggenError::TaskTimedOut {
+                task: task_name.to_string(),
+                timeout,
+            });
+        };
+        if let (0, Some(key), Some(stdout)) = (code, &cache_key, &stdout) {
+            cache.store(key, &task.module.dir, &task.attrs.outputs, stdout)?;
+        }
         let msg = if code == 0 {
             format!("✓ done: {}", task_name)
+        } else if attempt > 1 {
+            format!(
+                "✗ failed: {} ({}) after {} attempts",
+                task_name, code, attempt
+            )
         } else {
             format!("✗ failed: {} ({})", task_name, code)
         };
@@ -165,35 +556,69 @@ impl<'a> Friggen<'a> {
         Ok(code)
     }
 
-    fn build_var_map(
+    /// Run a task script line by line, make style, stopping at the first failing command.
+    /// Returns `None` if the task timed out.
+    fn run_task_lines(
         &self,
-        el: &'a AstNode,
-        vars: &mut HashMap<&'a str, Cow<'a, str>>,
-    ) -> Result<()> {
-        match el {
-            AstNode::Root(body) => {
-                for el in body {
-                    self.build_var_map(el, vars)?;
-                }
+        task: &Task<'_>,
+        interpreter: &Interpreter,
+        script_args: &[&str],
+        env: &HashMap<&str, &str>,
+        timeout: Option<Duration>,
+        mut stdout: Option<&mut Vec<u8>>,
+    ) -> Result<Option<i32>> {
+        let start = Instant::now();
+        let working_dir = task.working_dir(&self.fs_context.working_dir);
+
+        for command in split_commands(&task.script) {
+            if command.echo {
+                self.output_printer.print_command(&command.command);
             }
-            AstNode::VarAssignment(var) => {
-                let name = var.name;
-                let value = match var.value.as_ref() {
-                    AstNode::VarValue(value) => Cow::from(*value),
-                    AstNode::CommandSubstitution(command) => {
-                        let output = eval_shell_command("bash", command, &self.env_vars)?;
-                        // Mimic shell behaviour of removing trailing newlines in command substitution
-                        let output = output.trim_end_matches(&['\r', '\n']).to_string();
-                        Cow::from(output)
-                    }
-                    _ => unreachable!(),
-                };
-                vars.insert(name, value);
+
+            // The timeout is for the whole task
+            let timeout = timeout.map(|t| t.saturating_sub(start.elapsed()));
+            let code = run_shell_command(
+                interpreter,
+                &command.command,
+                script_args,
+                &working_dir,
+                env,
+                timeout,
+                stdout.as_deref_mut(),
+            )?;
+            match code {
+                Some(code) if code != 0 && !command.ignore_failure => return Ok(Some(code)),
+                Some(_) => {}
+                None => return Ok(None),
             }
-            _ => {}
         }
-        Ok(())
+
+        Ok(Some(0))
+    }
+}
+
+/// Drop variables that are set in the environment friggen runs in, which env files don't
+/// override.
+fn retain_unexported(vars: &mut Vec<(String, String)>) {
+    vars.retain(|(name, _)| env::var_os(name).is_none());
+}
+
+/// The env file variables a task sees: the ones every task sees, followed by the ones from the
+/// `@env_file` files of the task, which are relative to its friggenfile.
+fn load_task_env_files(
+    task: &Task<'_>,
+    env_file_vars: &[(String, String)],
+) -> Result<Vec<(String, String)>> {
+    let mut vars = env_file_vars.to_vec();
+    for env_file in &task.attrs.env_files {
+        vars.extend(read_env_file(&task.module.dir.join(env_file))?);
     }
+    retain_unexported(&mut vars);
+    Ok(vars)
+}
+
+fn str_map(map: &HashMap<String, String>) -> HashMap<&str, &str> {
+    map.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
 }
 
 #[inline]
@@ -205,56 +630,207 @@ fn print_theme_for_code(code: i32) -> PrintTheme {
     }
 }
 
-fn build_task_map<'a>(el: &'a AstNode, tasks: &mut HashMap<&'a str, Task<'a>>) -> Result<()> {
-    match el {
-        AstNode::Root(body) => {
-            for el in body {
-                build_task_map(el, tasks)?;
-            }
+/// The arguments of a built-in command like `friggen help`, if that's what the command line
+/// asks for. A task or alias with the name of the command wins, so that it can still be run.
+fn command_args<'a, 'b>(
+    command: &str,
+    args: &'b [&'a str],
+    tasks: &HashMap<Cow<'_, str>, Task<'_>>,
+    aliases: &HashMap<Cow<'_, str>, Cow<'_, str>>,
+) -> Option<&'b [&'a str]> {
+    match args {
+        [first, rest @ ..]
+            if *first == command
+                && !tasks.contains_key(command)
+                && !aliases.contains_key(command) =>
+        {
+            Some(rest)
         }
-        AstNode::TaskDef(def) => {
-            let header = def.header.as_task_header();
-            let deps: Vec<TaskDep> = header
-                .deps
-                .iter()
-                .map(|dep| {
-                    let dep = dep.as_task_dep();
-                    TaskDep {
-                        name: dep.name,
-                        run_always: dep.run_always,
-                    }
+        _ => None,
+    }
+}
+
+/// What `friggen cache` is asked to do.
+#[derive(Debug, PartialEq)]
+enum CacheCommand {
+    Stats,
+    Clean { older_than: Option<Duration> },
+}
+
+impl CacheCommand {
+    fn parse(args: &[&str], older_than: Option<Duration>) -> Result<Self> {
+        let command = match args {
+            [] => {
+                return Err(FriggenError::MissingTaskArgument {
+                    task: CACHE_COMMAND.to_string(),
+                    param: "stats|clean".to_string(),
                 })
-                .collect();
-            let docs = def.docs.as_ref().map(|docs| docs.as_task_docs().clone());
+            }
+            ["stats"] if older_than.is_none() => Self::Stats,
+            ["clean"] => Self::Clean { older_than },
+            ["stats"] => return Err(older_than_error()),
+            [arg] | [_, arg, ..] => {
+                return Err(FriggenError::UnexpectedTaskArgument {
+                    task: CACHE_COMMAND.to_string(),
+                    arg: arg.to_string(),
+                })
+            }
+        };
+        Ok(command)
+    }
+}
+
+fn older_than_error() -> FriggenError {
+    FriggenError::UnexpectedOption {
+        option: "--older-than".to_string(),
+        command: format!("{} clean", CACHE_COMMAND),
+    }
+}
+
+/// Run `friggen cache stats` or `friggen cache clean`, given the arguments after `cache`.
+pub fn run_cache_command(args: &[&str], older_than: Option<Duration>) -> Result<()> {
+    let cache = TaskCache::open();
+    match CacheCommand::parse(args, older_than)? {
+        CacheCommand::Stats => {
+            let stats = cache.stats()?;
+            println!("entries: {}", stats.entries);
+            println!("size:    {}", format_size(stats.size));
+            if let (Some(oldest), Some(newest)) = (stats.oldest, stats.newest) {
+                println!("oldest:  {}", format_age(oldest));
+                println!("newest:  {}", format_age(newest));
+            }
+        }
+        CacheCommand::Clean { older_than } => {
+            let removed = cache.clean(older_than)?;
+            println!("removed {} cache entries", removed);
+        }
+    }
+    Ok(())
+}
+
+/// Build a map of every task by its qualified name, along with a map of task aliases to the
+/// names of the tasks they stand for. Dependencies named by an alias are resolved to the name of
+/// the task.
+pub(crate) fn build_task_map<'a>(
+    items: &[Item<'a, 'a>],
+    tasks: &mut HashMap<Cow<'a, str>, Task<'a>>,
+    aliases: &mut HashMap<Cow<'a, str>, Cow<'a, str>>,
+) -> Result<()> {
+    // The group from the last `###` heading of each module
+    let mut groups: HashMap<Option<&str>, &str> = HashMap::new();
 
-            let script = def.script.as_task_script();
+    for item in items {
+        let def = match item.node {
+            AstNode::TaskDef(def) => def,
+            AstNode::Group(group) => {
+                groups.insert(item.module.namespace.as_deref(), group);
+                continue;
+            }
+            _ => continue,
+        };
+
+        let header = def.header.as_task_header();
+        let params: Vec<TaskParam> = header
+            .params
+            .iter()
+            .map(|param| {
+                let param = param.as_task_param();
+                TaskParam {
+                    name: param.name,
+                    default: param.default,
+                }
+            })
+            .collect();
+        let deps: Vec<TaskDep> = header
+            .deps
+            .iter()
+            .map(|dep| {
+                let dep = dep.as_task_dep();
+                TaskDep {
+                    // Dependencies are relative to the friggenfile they're declared in
+                    name: item.module.qualify(dep.name),
+                    run_always: dep.run_always,
+                }
+            })
+            .collect();
+        let docs = def
+            .docs
+            .as_ref()
+            .map(|docs| TaskDocs::parse(docs.as_task_docs()))
+            .unwrap_or_default();
+        let attrs = TaskAttributes::from_ast(header.name, &def.attrs)?;
+        let group = attrs
+            .group
+            .or_else(|| groups.get(&item.module.namespace.as_deref()).copied());
+
+        let script = def.script.as_task_script();
 
-            let task_name = header.name;
+        let task_name = item.module.qualify(header.name);
+        let task_aliases: Vec<Cow<str>> = header
+            .aliases
+            .iter()
+            .map(|alias| item.module.qualify(alias))
+            .collect();
 
+        for name in std::iter::once(&task_name).chain(&task_aliases) {
             // Ugh: https://github.com/rust-lang/rust/issues/82766
-            if tasks.contains_key(task_name) {
-                return Err(FriggenError::DuplicateTaskDefinition(task_name.to_string()));
+            let existing = tasks
+                .get(name)
+                .or_else(|| aliases.get(name).map(|task_name| &tasks[task_name]));
+            if let Some(existing) = existing {
+                return Err(FriggenError::DuplicateTaskDefinition {
+                    task: name.to_string(),
+                    first: existing.source.to_string_lossy().to_string(),
+                    second: item.source.to_string_lossy().to_string(),
+                });
+            }
+        }
+
+        for alias in &task_aliases {
+            aliases.insert(alias.clone(), task_name.clone());
+        }
+        tasks.insert(
+            task_name.clone(),
+            Task {
+                name: task_name,
+                aliases: task_aliases,
+                order: tasks.len(),
+                group,
+                module: item.module,
+                source: item.source,
+                attrs,
+                params,
+                deps,
+                docs,
+                hash_bang: script.hash_bang.clone(),
+                script: script.lines.clone(),
+            },
+        );
+    }
+
+    for task in tasks.values_mut() {
+        for dep in &mut task.deps {
+            if let Some(name) = aliases.get(&dep.name) {
+                dep.name = name.clone();
             }
-            tasks.insert(
-                task_name,
-                Task {
-                    name: header.name,
-                    deps,
-                    docs,
-                    hash_bang: script.hash_bang.clone(),
-                    script: script.lines.clone(),
-                },
-            );
-        }
-        _ => {}
+        }
     }
+
     Ok(())
 }
 
-fn validate_tasks(tasks: &HashMap<&str, Task<'_>>) -> Result<()> {
+fn validate_tasks(tasks: &HashMap<Cow<'_, str>, Task<'_>>) -> Result<()> {
     for task in tasks.values() {
+        for (i, param) in task.params.iter().enumerate() {
+            if task.params[..i].iter().any(|p| p.name == param.name) {
+                return Err(FriggenError::DuplicateTaskParameter {
+                    task: task.name.to_string(),
+                    param: param.name.to_string(),
+                });
+            }
+        }
         for dep in &task.deps {
-            if !tasks.contains_key(dep.name) {
+            if !tasks.contains_key(&dep.name) {
                 return Err(FriggenError::InvalidTaskReference {
                     referrer: task.name.to_string(),
                     referee: dep.name.to_string(),
@@ -265,9 +841,140 @@ fn validate_tasks(tasks: &HashMap<&str, Task<'_>>) -> Result<()> {
     Ok(())
 }
 
+/// Split command line arguments into task invocations. An argument naming a task starts a new
+/// invocation, and any other argument is given to the parameters of the preceding task, either
+/// by name (`param=value`) or by position. An argument that looks like `param=value` but names
+/// no parameter is an error, rather than a value for the next parameter.
+fn parse_task_invocations<'a>(
+    args: &[&'a str],
+    tasks: &'a HashMap<Cow<'a, str>, Task<'a>>,
+    aliases: &HashMap<Cow<'a, str>, Cow<'a, str>>,
+) -> Result<Vec<TaskInvocation<'a>>> {
+    let mut invocations: Vec<TaskInvocation> = Vec::with_capacity(args.len());
+
+    for arg in args {
+        let task_name = aliases.get(*arg).map(|name| name.as_ref()).unwrap_or(arg);
+        if let Some(task) = tasks.get(task_name) {
+            invocations.push(TaskInvocation {
+                name: &task.name,
+                args: HashMap::new(),
+            });
+            continue;
+        }
+
+        let Some(invocation) = invocations.last_mut() else {
+            return Err(FriggenError::TaskNotFound(arg.to_string()));
+        };
+        let task = &tasks[invocation.name];
+
+        if let Some((name, value)) = arg.split_once('=') {
+            if let Some(param) = task.params.iter().find(|p| p.name == name) {
+                invocation.args.insert(param.name, value);
+                continue;
+            }
+            if is_param_name(name) {
+                return Err(FriggenError::UnexpectedTaskArgument {
+                    task: task.name.to_string(),
+                    arg: arg.to_string(),
+                });
+            }
+        }
+
+        let param = task
+            .params
+            .iter()
+            .find(|p| !invocation.args.contains_key(p.name))
+            .ok_or_else(|| FriggenError::UnexpectedTaskArgument {
+                task: task.name.to_string(),
+                arg: arg.to_string(),
+            })?;
+        invocation.args.insert(param.name, arg);
+    }
+
+    Ok(invocations)
+}
+
+/// Whether a string could name a task parameter.
+fn is_param_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Resolve the value of every parameter of a task, falling back to parameter defaults for
+/// tasks that weren't given arguments, such as dependencies.
+fn resolve_task_args<'a>(
+    task: &Task<'a>,
+    invocation: Option<&TaskInvocation<'a>>,
+) -> Result<HashMap<&'a str, &'a str>> {
+    let mut args = HashMap::with_capacity(task.params.len());
+    for param in &task.params {
+        let value = invocation
+            .and_then(|inv| inv.args.get(param.name).copied())
+            .or(param.default)
+            .ok_or_else(|| FriggenError::MissingTaskArgument {
+                task: task.name.to_string(),
+                param: param.name.to_string(),
+            })?;
+        args.insert(param.name, value);
+    }
+    Ok(args)
+}
+
+/// Sort tasks for the task listing, by module, then group, then task. In source order, modules
+/// and groups are in the order they first appear.
+fn sort_tasks(tasks: &mut [&Task<'_>], order: ListOrder) {
+    match order {
+        ListOrder::Alpha => tasks.sort_by(|a, b| {
+            let a_key = (a.module.namespace.as_deref(), a.group, &a.name);
+            let b_key = (b.module.namespace.as_deref(), b.group, &b.name);
+            a_key.cmp(&b_key)
+        }),
+        ListOrder::Source => {
+            let mut first_namespaces: HashMap<Option<&str>, usize> = HashMap::new();
+            let mut first_groups: HashMap<(Option<&str>, Option<&str>), usize> = HashMap::new();
+            for task in tasks.iter() {
+                let namespace = task.module.namespace.as_deref();
+                let first = first_namespaces.entry(namespace).or_insert(task.order);
+                *first = (*first).min(task.order);
+                let first = first_groups
+                    .entry((namespace, task.group))
+                    .or_insert(task.order);
+                *first = (*first).min(task.order);
+            }
+            tasks.sort_by_key(|task| {
+                let namespace = task.module.namespace.as_deref();
+                (
+                    first_namespaces[&namespace],
+                    first_groups[&(namespace, task.group)],
+                    task.order,
+                )
+            });
+        }
+    }
+}
+
+fn task_signature(task: &Task<'_>) -> String {
+    let mut sig = task.name.to_string();
+    for alias in &task.aliases {
+        sig.push('|');
+        // Aliases are qualified the same way as the task name, so only show the local part
+        sig.push_str(alias.rsplit(':').next().unwrap_or_default());
+    }
+    for param in &task.params {
+        sig.push(' ');
+        sig.push_str(param.name);
+        if let Some(default) = param.default {
+            sig.push('=');
+            sig.push_str(default);
+        }
+    }
+    sig
+}
+
 fn build_task_sequence<'a>(
     requested_tasks: &'a [&str],
-    tasks: &'a HashMap<&str, Task<'_>>,
+    tasks: &'a HashMap<Cow<'_, str>, Task<'_>>,
 ) -> Result<Vec<&'a str>> {
     let mut seq: Vec<&str> = Vec::with_capacity(32);
     let mut stack: Vec<&str> = Vec::with_capacity(16);
@@ -284,7 +991,7 @@ fn build_task_sequence<'a>(
 
 fn resolve_task_sequence<'a>(
     task_name: &'a str,
-    tasks: &'a HashMap<&'a str, Task<'_>>,
+    tasks: &'a HashMap<Cow<'_, str>, Task<'_>>,
     seq: &mut Vec<&'a str>,
     stack: &mut Vec<&'a str>,
 ) -> Result<()> {
@@ -302,9 +1009,9 @@ fn resolve_task_sequence<'a>(
         .ok_or_else(|| FriggenError::TaskNotFound(task_name.to_string()))?;
 
     for dep in &task.deps {
-        resolve_task_sequence(dep.name, tasks, seq, stack)?;
-        if dep.run_always || !seq.contains(&dep.name) {
-            seq.push(dep.name);
+        resolve_task_sequence(&dep.name, tasks, seq, stack)?;
+        if dep.run_always || !seq.contains(&dep.name.as_ref()) {
+            seq.push(&dep.name);
         }
     }
 
@@ -313,3 +1020,319 @@ fn resolve_task_sequence<'a>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use clap::Parser;
+
+    use crate::args::Args;
+    use crate::error::FriggenError;
+    use crate::friggen::{
+        build_task_map, command_args, load_task_env_files, parse_task_invocations,
+        resolve_task_args, CacheCommand, CACHE_COMMAND, HELP_COMMAND,
+    };
+    use crate::friggenfile::{load_sources, Friggenfile, Source, Task};
+    use crate::interpreter::Interpreters;
+
+    fn load(dir: &Path, text: &str) -> Vec<Source> {
+        fs::write(dir.join("friggenfile"), text).unwrap();
+        load_sources(&dir.join("friggenfile")).unwrap()
+    }
+
+    type TaskMap<'a> = HashMap<Cow<'a, str>, Task<'a>>;
+    type AliasMap<'a> = HashMap<Cow<'a, str>, Cow<'a, str>>;
+    type TaskArgs<'a> = HashMap<&'a str, &'a str>;
+
+    fn task_maps<'a>(ff: &'a Friggenfile<'a>) -> (TaskMap<'a>, AliasMap<'a>) {
+        let mut tasks = HashMap::new();
+        let mut aliases = HashMap::new();
+        build_task_map(&ff.items(), &mut tasks, &mut aliases).unwrap();
+        (tasks, aliases)
+    }
+
+    #[test]
+    fn test_command_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = load(dir.path(), "deploy:\n  ./deploy.sh\n");
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, aliases) = task_maps(&ff);
+        let help_args = |args| command_args(HELP_COMMAND, args, &tasks, &aliases);
+
+        assert_eq!(help_args(&["help", "deploy"]), Some(&["deploy"][..]));
+        assert_eq!(help_args(&["help"]), Some(&[][..]));
+        assert_eq!(help_args(&["deploy", "help"]), None);
+        assert_eq!(help_args(&[]), None);
+        assert_eq!(
+            command_args(CACHE_COMMAND, &["cache", "stats"], &tasks, &aliases),
+            Some(&["stats"][..])
+        );
+        assert_eq!(help_args(&["cache", "stats"]), None);
+    }
+
+    #[test]
+    fn test_task_beats_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = load(dir.path(), "help:\n  echo halp\n\ncache:\n  echo stash\n");
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, aliases) = task_maps(&ff);
+        assert_eq!(
+            command_args(HELP_COMMAND, &["help", "deploy"], &tasks, &aliases),
+            None
+        );
+        assert_eq!(
+            command_args(CACHE_COMMAND, &["cache", "stats"], &tasks, &aliases),
+            None
+        );
+
+        let sources = load(dir.path(), "assist|help:\n  echo halp\n");
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, aliases) = task_maps(&ff);
+        assert_eq!(
+            command_args(HELP_COMMAND, &["help"], &tasks, &aliases),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_command() {
+        let week = Some(Duration::from_secs(7 * 86400));
+        assert_eq!(
+            CacheCommand::parse(&["stats"], None).unwrap(),
+            CacheCommand::Stats
+        );
+        assert_eq!(
+            CacheCommand::parse(&["clean"], week).unwrap(),
+            CacheCommand::Clean { older_than: week }
+        );
+        assert!(matches!(
+            CacheCommand::parse(&[], None),
+            Err(FriggenError::MissingTaskArgument { .. })
+        ));
+        assert!(matches!(
+            CacheCommand::parse(&["purge"], None),
+            Err(FriggenError::UnexpectedTaskArgument { arg, .. }) if arg == "purge"
+        ));
+        assert!(matches!(
+            CacheCommand::parse(&["clean", "now"], None),
+            Err(FriggenError::UnexpectedTaskArgument { arg, .. }) if arg == "now"
+        ));
+        assert!(matches!(
+            CacheCommand::parse(&["stats"], week),
+            Err(FriggenError::UnexpectedOption { .. })
+        ));
+    }
+
+    const DEPLOY: &str = "\
+build target=debug:
+  make
+
+deploy env region=us: build
+  ./deploy.sh
+";
+
+    /// The arguments each task of a command line resolves to, in order.
+    fn task_args<'a>(
+        tasks: &'a TaskMap<'a>,
+        aliases: &AliasMap<'a>,
+        args: &[&'a str],
+    ) -> Result<Vec<(&'a str, TaskArgs<'a>)>, FriggenError> {
+        let invocations = parse_task_invocations(args, tasks, aliases)?;
+        invocations
+            .iter()
+            .map(|inv| Ok((inv.name, resolve_task_args(&tasks[inv.name], Some(inv))?)))
+            .collect()
+    }
+
+    #[test]
+    fn test_task_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = load(dir.path(), DEPLOY);
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, aliases) = task_maps(&ff);
+        let deploy = |env, region| HashMap::from([("env", env), ("region", region)]);
+
+        // Positional, then named, in any order
+        assert_eq!(
+            task_args(&tasks, &aliases, &["deploy", "prod", "eu"]).unwrap(),
+            [("deploy", deploy("prod", "eu"))]
+        );
+        assert_eq!(
+            task_args(&tasks, &aliases, &["deploy", "region=eu", "prod"]).unwrap(),
+            [("deploy", deploy("prod", "eu"))]
+        );
+        assert_eq!(
+            task_args(&tasks, &aliases, &["deploy", "env=a=b"]).unwrap(),
+            [("deploy", deploy("a=b", "us"))]
+        );
+        // Values that can't be parameter names are positional, `=` or not
+        assert_eq!(
+            task_args(&tasks, &aliases, &["deploy", "--x=1"]).unwrap(),
+            [("deploy", deploy("--x=1", "us"))]
+        );
+        assert_eq!(
+            task_args(&tasks, &aliases, &["build", "release", "deploy", "prod"]).unwrap(),
+            [
+                ("build", HashMap::from([("target", "release")])),
+                ("deploy", deploy("prod", "us")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_task_args_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = load(dir.path(), DEPLOY);
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, aliases) = task_maps(&ff);
+
+        assert!(matches!(
+            task_args(&tasks, &aliases, &["deploy"]),
+            Err(FriggenError::MissingTaskArgument { task, param }) if task == "deploy" && param == "env"
+        ));
+        assert!(matches!(
+            task_args(&tasks, &aliases, &["deploy", "prod", "regoin=eu"]),
+            Err(FriggenError::UnexpectedTaskArgument { task, arg })
+                if task == "deploy" && arg == "regoin=eu"
+        ));
+        assert!(matches!(
+            task_args(&tasks, &aliases, &["deploy", "prod", "eu", "extra"]),
+            Err(FriggenError::UnexpectedTaskArgument { arg, .. }) if arg == "extra"
+        ));
+        assert!(matches!(
+            task_args(&tasks, &aliases, &["prod"]),
+            Err(FriggenError::TaskNotFound(name)) if name == "prod"
+        ));
+    }
+
+    #[test]
+    fn test_dependency_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = load(dir.path(), DEPLOY);
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, _) = task_maps(&ff);
+
+        // Dependencies aren't invoked, so they get their defaults
+        assert_eq!(
+            resolve_task_args(&tasks["build"], None).unwrap(),
+            HashMap::from([("target", "debug")])
+        );
+        assert!(matches!(
+            resolve_task_args(&tasks["deploy"], None),
+            Err(FriggenError::MissingTaskArgument { param, .. }) if param == "env"
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_task_names_both_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("ci.friggen"), "build:\n  make ci\n").unwrap();
+        let sources = load(dir.path(), "build:\n  make\n\ninclude \"ci.friggen\"\n");
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let mut tasks = HashMap::new();
+        let mut aliases = HashMap::new();
+
+        let err = build_task_map(&ff.items(), &mut tasks, &mut aliases).unwrap_err();
+        let FriggenError::DuplicateTaskDefinition {
+            task,
+            first,
+            second,
+        } = err
+        else {
+            panic!("expected a duplicate task, got {:?}", err);
+        };
+        assert_eq!(task, "build");
+        assert!(first.ends_with("/friggenfile"));
+        assert!(second.ends_with("/ci.friggen"));
+    }
+
+    #[test]
+    fn test_load_task_env_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(".env.prod"),
+            "FRIGGEN_TEST_REGION=eu\nFRIGGEN_TEST_TIER=prod\n",
+        )
+        .unwrap();
+        let sources = load(
+            dir.path(),
+            "build:\n  make\n\n@env_file(\".env.prod\")\ndeploy:\n  ./deploy.sh\n",
+        );
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, _) = task_maps(&ff);
+        let global = vec![
+            ("FRIGGEN_TEST_REGION".to_string(), "us".to_string()),
+            ("FRIGGEN_TEST_DEBUG".to_string(), "1".to_string()),
+        ];
+
+        assert_eq!(
+            load_task_env_files(&tasks["build"], &global).unwrap(),
+            global
+        );
+        let vars: HashMap<String, String> = load_task_env_files(&tasks["deploy"], &global)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(vars["FRIGGEN_TEST_REGION"], "eu");
+        assert_eq!(vars["FRIGGEN_TEST_TIER"], "prod");
+        assert_eq!(vars["FRIGGEN_TEST_DEBUG"], "1");
+    }
+
+    #[test]
+    fn test_lines_mode_needs_a_shell() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources = load(
+            dir.path(),
+            r#"
+@mode(lines)
+shell:
+  echo hi
+
+@mode(lines)
+@interpreter(node)
+node:
+  console.log("hi")
+
+@mode(lines)
+perl:
+  #!/usr/bin/env perl
+  print "hi\n";
+
+@mode(lines)
+sh:
+  #!/bin/sh -x
+  echo hi
+"#,
+        );
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let (tasks, _) = task_maps(&ff);
+        let interpreters = Interpreters::from_ast(ff.items().iter().map(|item| item.node)).unwrap();
+
+        assert!(interpreters.for_task(&tasks["shell"], "bash", true).is_ok());
+        assert!(interpreters.for_task(&tasks["sh"], "bash", true).is_ok());
+        for name in ["node", "perl"] {
+            let err = interpreters
+                .for_task(&tasks[name], "bash", true)
+                .unwrap_err();
+            assert!(matches!(
+                err,
+                FriggenError::InvalidTaskAttribute { task, attr, .. }
+                    if task == name && attr == "mode"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_help_with_options() {
+        let args =
+            Args::try_parse_from(["friggen", "-f", "friggenfile", "--all", "help", "deploy"])
+                .unwrap();
+        assert!(args.all);
+        assert_eq!(args.tasks, ["help", "deploy"]);
+    }
+}
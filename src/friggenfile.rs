@@ -1,35 +1,1247 @@
-use crate::ast::AstNode;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::ast::{AstNode, AstTaskAttr};
+use crate::cache::uncacheable_output;
+use crate::duration::parse_duration;
 use crate::error::{FriggenError, Result};
+use crate::fs_context::contains_friggenfile;
+use crate::ioutil::read_file;
 use crate::parser::parse_friggenfile;
 
 #[derive(Debug, Clone)]
 pub struct Task<'src> {
-    pub name: &'src str,
-    pub docs: Option<Vec<&'src str>>,
+    pub name: Cow<'src, str>,
+    pub aliases: Vec<Cow<'src, str>>,
+    /// The position of the task definition among all task definitions.
+    pub order: usize,
+    /// The group the task is listed under, from a `### Group` heading or `@group`.
+    pub group: Option<&'src str>,
+    pub module: &'src Module,
+    pub source: &'src Path,
+    pub attrs: TaskAttributes<'src>,
+    pub docs: TaskDocs<'src>,
+    pub params: Vec<TaskParam<'src>>,
     pub deps: Vec<TaskDep<'src>>,
     pub hash_bang: Option<Vec<&'src str>>,
     pub script: Vec<&'src str>,
 }
 
+impl<'src> Task<'src> {
+    /// Private tasks are hidden from the task listing, and are meant to be run as dependencies.
+    /// A task is private when its name starts with an underscore, or it has `@private`.
+    #[inline]
+    pub fn is_private(&self) -> bool {
+        let local_name = self.name.rsplit(':').next().unwrap_or_default();
+        self.attrs.private || local_name.starts_with('_')
+    }
+
+    /// Whether every output of the task is newer than all of its sources, so that it doesn't
+    /// need to run. Tasks without `@outputs` are never up to date, and tasks with `@outputs` but
+    /// no `@sources` are up to date as soon as their outputs exist.
+    pub fn is_up_to_date(&self) -> Result<bool> {
+        if self.attrs.outputs.is_empty() {
+            return Ok(false);
+        }
+
+        let mut oldest_output: Option<SystemTime> = None;
+        for output in &self.attrs.outputs {
+            let Ok(meta) = fs::metadata(self.module.dir.join(output)) else {
+                return Ok(false);
+            };
+            let modified = meta.modified()?;
+            oldest_output = Some(oldest_output.map_or(modified, |oldest| oldest.min(modified)));
+        }
+
+        for path in self.source_files()? {
+            if Some(fs::metadata(path)?.modified()?) > oldest_output {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// The files matching the `@sources` globs of the task, in alphabetical order.
+    pub fn source_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        for source in &self.attrs.sources {
+            let pattern = self.module.dir.join(source);
+            for path in glob::glob(&pattern.to_string_lossy()).map_err(anyhow::Error::from)? {
+                let path = path.map_err(anyhow::Error::from)?;
+                if path.is_file() && !files.contains(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// The directory the task script runs in, where `root` is the directory tasks of the root
+    /// friggenfile run in.
+    pub fn working_dir(&self, root: &Path) -> PathBuf {
+        match self.attrs.dir {
+            Some(dir) => self.module.dir.join(dir),
+            None => self.module.working_dir(root).to_path_buf(),
+        }
+    }
+}
+
+/// Task documentation from the `##` lines above a task.
+///
+/// The first paragraph is a summary, shown in the task listing, and any other paragraphs are a
+/// longer description. Lines starting with a tag, like `## @example friggen deploy staging`,
+/// are kept separately.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaskDocs<'src> {
+    pub summary: Vec<&'src str>,
+    /// Description lines, with an empty line between paragraphs.
+    pub description: Vec<&'src str>,
+    pub tags: Vec<DocTag<'src>>,
+}
+
+/// A tagged documentation line, like `@env AWS_PROFILE required`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocTag<'src> {
+    pub name: &'src str,
+    pub text: &'src str,
+}
+
+impl<'src> TaskDocs<'src> {
+    pub fn parse(lines: &[&'src str]) -> Self {
+        let mut docs = Self::default();
+        let mut in_summary = true;
+
+        for line in lines {
+            let line = line.trim();
+            if let Some(tag) = line.strip_prefix('@') {
+                let (name, text) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                docs.tags.push(DocTag {
+                    name,
+                    text: text.trim(),
+                });
+                in_summary = false;
+            } else if line.is_empty() {
+                in_summary = docs.summary.is_empty();
+                if !docs.description.is_empty() && docs.description.last() != Some(&"") {
+                    docs.description.push(line);
+                }
+            } else if in_summary {
+                docs.summary.push(line);
+            } else {
+                docs.description.push(line);
+            }
+        }
+
+        if docs.description.last() == Some(&"") {
+            docs.description.pop();
+        }
+        docs
+    }
+
+    /// The summary as a single line.
+    #[inline]
+    pub fn summary(&self) -> String {
+        self.summary.join(" ")
+    }
+}
+
+/// Task behaviours configured with attribute lines above the task header, like `@private`.
+#[derive(Debug, Clone, Default)]
+pub struct TaskAttributes<'src> {
+    /// `@private`: Hide the task from the task listing.
+    pub private: bool,
+    /// `@confirm`: Ask before running the task.
+    pub confirm: bool,
+    /// `@dir(path)`: Run the task in a directory relative to its friggenfile.
+    pub dir: Option<&'src str>,
+    /// `@timeout(duration)`: Kill the task if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// `@env_file(path, ...)`: Load environment variables from files relative to the friggenfile.
+    pub env_files: Vec<&'src str>,
+    /// `@interpreter(name)`: Run the script with a named interpreter instead of the shell.
+    pub interpreter: Option<&'src str>,
+    /// `@strict` or `@strict(false)`: Override the `strict` setting for the task.
+    pub strict: Option<bool>,
+    /// `@mode(script|lines)`: Run the script as a whole, or line by line.
+    pub mode: ScriptMode,
+    /// `@group(name)`: List the task under a group, instead of the one from a `###` heading.
+    pub group: Option<&'src str>,
+    /// `@sources(glob, ...)`: Files the task reads, relative to the friggenfile.
+    pub sources: Vec<&'src str>,
+    /// `@outputs(path, ...)`: Files the task writes, relative to the friggenfile. The task is
+    /// skipped when they're all newer than its sources.
+    pub outputs: Vec<&'src str>,
+    /// `@cache`: Cache the outputs and printed output of the task by the content of its
+    /// script, environment and sources.
+    pub cache: bool,
+    /// `@retry(attempts, backoff=fixed|exponential, delay=duration, codes=code|...)`: Run the
+    /// task again when it fails.
+    pub retry: Option<RetryPolicy>,
+}
+
+/// How often and how patiently a failing task is run again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// How many times the task runs at most, counting the first run.
+    pub attempts: u32,
+    pub backoff: Backoff,
+    /// How long to wait before the first retry.
+    pub delay: Duration,
+    /// The exit codes worth retrying. Any failure is, if there are none.
+    pub codes: Vec<i32>,
+}
+
+/// How the wait between retries grows.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Backoff {
+    /// Wait the same time before every retry.
+    #[default]
+    Fixed,
+    /// Double the wait before every retry.
+    Exponential,
+}
+
+impl RetryPolicy {
+    const DEFAULT_DELAY: Duration = Duration::from_secs(1);
+
+    fn from_attr(task: &str, attr: &AstTaskAttr) -> Result<Self> {
+        let (args, keyed) = attr_keyed_args(task, attr, 1, &["backoff", "delay", "codes"])?;
+
+        let attempts = args[0]
+            .parse::<u32>()
+            .ok()
+            .filter(|&attempts| attempts > 0)
+            .ok_or_else(|| {
+                invalid_attr(
+                    task,
+                    attr,
+                    format!("invalid number of attempts: {}", args[0]),
+                )
+            })?;
+        let mut policy = Self {
+            attempts,
+            backoff: Backoff::default(),
+            delay: Self::DEFAULT_DELAY,
+            codes: vec![],
+        };
+
+        for (key, value) in keyed {
+            match key {
+                "backoff" => {
+                    policy.backoff = match value {
+                        "fixed" => Backoff::Fixed,
+                        "exponential" => Backoff::Exponential,
+                        value => {
+                            let reason = format!("expected fixed or exponential: {}", value);
+                            return Err(invalid_attr(task, attr, reason));
+                        }
+                    };
+                }
+                "delay" => {
+                    policy.delay = parse_duration(value).ok_or_else(|| {
+                        invalid_attr(task, attr, format!("invalid duration: {}", value))
+                    })?;
+                }
+                "codes" => {
+                    for code in value.split('|') {
+                        let code = code.trim().parse::<i32>().map_err(|_| {
+                            invalid_attr(task, attr, format!("invalid exit code: {}", code))
+                        })?;
+                        policy.codes.push(code);
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Whether a task should run again after failing `attempt` times, the last time with `code`.
+    pub fn should_retry(&self, attempt: u32, code: i32) -> bool {
+        code != 0
+            && attempt < self.attempts
+            && (self.codes.is_empty() || self.codes.contains(&code))
+    }
+
+    /// How long to wait after failing `attempt` times.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed => self.delay,
+            Backoff::Exponential => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                self.delay.saturating_mul(factor)
+            }
+        }
+    }
+}
+
+/// How a task script is run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ScriptMode {
+    /// Run the whole script with the interpreter.
+    #[default]
+    Script,
+    /// Run the script line by line, echoing each command and stopping at the first failure.
+    Lines,
+}
+
+impl<'src> TaskAttributes<'src> {
+    pub fn from_ast(task: &str, attrs: &[AstNode<'src>]) -> Result<Self> {
+        let mut attributes = Self::default();
+
+        for attr in attrs {
+            let attr = attr.as_task_attr();
+            match attr.name {
+                "private" => {
+                    attr_args(task, attr, 0, 0)?;
+                    attributes.private = true;
+                }
+                "confirm" => {
+                    attr_args(task, attr, 0, 0)?;
+                    attributes.confirm = true;
+                }
+                "dir" => {
+                    attributes.dir = Some(attr_args(task, attr, 1, 1)?[0]);
+                }
+                "timeout" => {
+                    let value = attr_args(task, attr, 1, 1)?[0];
+                    let timeout = parse_duration(value).ok_or_else(|| {
+                        invalid_attr(task, attr, format!("invalid duration: {}", value))
+                    })?;
+                    attributes.timeout = Some(timeout);
+                }
+                "env_file" => {
+                    let files = attr_args(task, attr, 1, usize::MAX)?;
+                    attributes.env_files.extend(files);
+                }
+                "interpreter" => {
+                    attributes.interpreter = Some(attr_args(task, attr, 1, 1)?[0]);
+                }
+                "strict" => {
+                    let strict = match attr_args(task, attr, 0, 1)?.first() {
+                        Some(value) => parse_bool(value).ok_or_else(|| {
+                            invalid_attr(task, attr, format!("expected true or false: {}", value))
+                        })?,
+                        None => true,
+                    };
+                    attributes.strict = Some(strict);
+                }
+                "retry" => {
+                    attributes.retry = Some(RetryPolicy::from_attr(task, attr)?);
+                }
+                "cache" => {
+                    attr_args(task, attr, 0, 0)?;
+                    attributes.cache = true;
+                }
+                "sources" => {
+                    let sources = attr_args(task, attr, 1, usize::MAX)?;
+                    attributes.sources.extend(sources);
+                }
+                "outputs" => {
+                    let outputs = attr_args(task, attr, 1, usize::MAX)?;
+                    attributes.outputs.extend(outputs);
+                }
+                "group" => {
+                    attributes.group = Some(attr_args(task, attr, 1, 1)?[0]);
+                }
+                "mode" => {
+                    attributes.mode = match attr_args(task, attr, 1, 1)?[0] {
+                        "script" => ScriptMode::Script,
+                        "lines" => ScriptMode::Lines,
+                        value => {
+                            let reason = format!("expected script or lines: {}", value);
+                            return Err(invalid_attr(task, attr, reason));
+                        }
+                    };
+                }
+                _ => {
+                    return Err(FriggenError::UnknownTaskAttribute {
+                        task: task.to_string(),
+                        attr: attr.name.to_string(),
+                    })
+                }
+            }
+        }
+
+        if attributes.cache {
+            for output in &attributes.outputs {
+                if let Some(reason) = uncacheable_output(output) {
+                    return Err(FriggenError::InvalidTaskAttribute {
+                        task: task.to_string(),
+                        attr: "outputs".to_string(),
+                        reason: format!("{}: {}", reason, output),
+                    });
+                }
+            }
+        }
+
+        Ok(attributes)
+    }
+}
+
+/// Get the arguments of an attribute that only takes positional arguments.
+fn attr_args<'src>(
+    task: &str,
+    attr: &AstTaskAttr<'src>,
+    min: usize,
+    max: usize,
+) -> Result<Vec<&'src str>> {
+    if let Some(arg) = attr.args.iter().find(|arg| arg.key.is_some()) {
+        let reason = format!("unexpected argument: {}", arg.key.unwrap());
+        return Err(invalid_attr(task, attr, reason));
+    }
+
+    let count = attr.args.len();
+    if count < min || count > max {
+        let reason = match (min, max) {
+            (0, 0) => "expected no arguments".to_string(),
+            (min, max) if min == max => format!("expected {} argument(s)", min),
+            (min, _) => format!("expected at least {} argument(s)", min),
+        };
+        return Err(invalid_attr(task, attr, reason));
+    }
+
+    Ok(attr.args.iter().map(|arg| arg.value).collect())
+}
+
+/// Get the arguments of an attribute that takes `count` positional arguments, followed by
+/// optional `key=value` arguments with the given keys.
+fn attr_keyed_args<'src>(
+    task: &str,
+    attr: &AstTaskAttr<'src>,
+    count: usize,
+    keys: &[&str],
+) -> Result<(Vec<&'src str>, HashMap<&'src str, &'src str>)> {
+    let mut args = vec![];
+    let mut keyed = HashMap::new();
+    for arg in &attr.args {
+        match arg.key {
+            None if !keyed.is_empty() => {
+                let reason = format!("positional argument after keyed ones: {}", arg.value);
+                return Err(invalid_attr(task, attr, reason));
+            }
+            None => args.push(arg.value),
+            Some(key) if !keys.contains(&key) => {
+                return Err(invalid_attr(
+                    task,
+                    attr,
+                    format!("unexpected argument: {}", key),
+                ));
+            }
+            Some(key) if keyed.contains_key(key) => {
+                return Err(invalid_attr(
+                    task,
+                    attr,
+                    format!("duplicate argument: {}", key),
+                ));
+            }
+            Some(key) => {
+                keyed.insert(key, arg.value);
+            }
+        }
+    }
+
+    if args.len() != count {
+        let reason = format!("expected {} argument(s)", count);
+        return Err(invalid_attr(task, attr, reason));
+    }
+
+    Ok((args, keyed))
+}
+
+#[inline]
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+#[inline]
+fn invalid_attr(task: &str, attr: &AstTaskAttr, reason: String) -> FriggenError {
+    FriggenError::InvalidTaskAttribute {
+        task: task.to_string(),
+        attr: attr.name.to_string(),
+        reason,
+    }
+}
+
+/// Friggenfile-wide behaviours configured with `set <name> <value>` lines.
+#[derive(Debug, Clone, Default)]
+pub struct Settings<'src> {
+    /// `set default <task>`: The task to run when no task is named on the command line.
+    pub default: Option<&'src str>,
+    /// `set shell <shell>`: The shell that runs command substitutions, and task scripts without
+    /// a shebang.
+    pub shell: Option<&'src str>,
+    /// `set strict <bool>`: Make shell task scripts stop at the first failing command.
+    pub strict: Option<bool>,
+    /// `set env_file <path>`: Load environment variables from a file relative to the
+    /// friggenfile, on top of `.env`.
+    pub env_files: Vec<&'src str>,
+}
+
+impl<'src> Settings<'src> {
+    const DEFAULT_SHELL: &'static str = "bash";
+
+    #[inline]
+    pub fn shell(&self) -> &'src str {
+        self.shell.unwrap_or(Self::DEFAULT_SHELL)
+    }
+
+    #[inline]
+    pub fn strict(&self) -> bool {
+        self.strict.unwrap_or(true)
+    }
+
+    /// Read settings from top-level statements. When a setting is set more than once, the last
+    /// value wins, except for `env_file`, which adds a file each time.
+    pub fn from_ast<'a>(nodes: impl IntoIterator<Item = &'a AstNode<'src>>) -> Result<Self>
+    where
+        'src: 'a,
+    {
+        let mut settings = Self::default();
+
+        for node in nodes {
+            let AstNode::Setting(setting) = node else {
+                continue;
+            };
+            match setting.name {
+                "default" => settings.default = Some(setting.value),
+                "shell" => settings.shell = Some(setting.value),
+                "env_file" => settings.env_files.push(setting.value),
+                "strict" => {
+                    let strict =
+                        parse_bool(setting.value).ok_or_else(|| FriggenError::InvalidSetting {
+                            setting: setting.name.to_string(),
+                            reason: format!("expected true or false: {}", setting.value),
+                        })?;
+                    settings.strict = Some(strict);
+                }
+                _ => return Err(FriggenError::UnknownSetting(setting.name.to_string())),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct TaskDep<'src> {
+pub struct TaskParam<'src> {
     pub name: &'src str,
-    pub run_always: bool,
+    pub default: Option<&'src str>,
 }
 
 #[derive(Debug, Clone)]
+pub struct TaskDep<'src> {
+    pub name: Cow<'src, str>,
+    pub run_always: bool,
+}
+
+/// A friggenfile source file, and the sources pulled in by each of its include and module
+/// statements.
+#[derive(Debug)]
+pub struct Source {
+    pub path: PathBuf,
+    pub text: String,
+    includes: Vec<Vec<usize>>,
+    modules: Vec<(String, usize)>,
+}
+
+/// A friggenfile mounted under a namespace. The root friggenfile is the module without one.
+#[derive(Debug)]
+pub struct Module {
+    pub namespace: Option<String>,
+    pub dir: PathBuf,
+    source: usize,
+}
+
+impl Module {
+    /// The directory tasks and command substitutions of this module run in. Modules run in
+    /// their own directory, and the root friggenfile runs in `root`.
+    #[inline]
+    pub fn working_dir<'a>(&'a self, root: &'a Path) -> &'a Path {
+        match self.namespace {
+            Some(_) => &self.dir,
+            None => root,
+        }
+    }
+
+    /// Qualify a task name, relative to this module, with the module namespace.
+    pub fn qualify<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match &self.namespace {
+            Some(ns) => Cow::from(format!("{}:{}", ns, name)),
+            None => Cow::from(name),
+        }
+    }
+}
+
+/// A top-level friggenfile statement, and the source file and module it came from.
+#[derive(Debug, Clone, Copy)]
+pub struct Item<'a, 'src> {
+    pub source: &'src Path,
+    pub module: &'a Module,
+    pub node: &'a AstNode<'src>,
+}
+
+#[derive(Debug)]
 pub struct Friggenfile<'src> {
-    ast: AstNode<'src>,
+    sources: &'src [Source],
+    asts: Vec<AstNode<'src>>,
+    modules: Vec<Module>,
 }
 
 impl<'src> Friggenfile<'src> {
-    pub fn from(buf: &'src str) -> Result<Self> {
-        let ast = parse_friggenfile(buf).map_err(FriggenError::FriggenfileSyntaxError)?;
-        Ok(Self { ast })
+    pub fn from_sources(sources: &'src [Source]) -> Result<Self> {
+        let asts = sources
+            .iter()
+            .map(parse_source)
+            .collect::<Result<Vec<_>>>()?;
+        let mut ff = Self {
+            sources,
+            asts,
+            modules: vec![],
+        };
+        ff.mount(0, None);
+        Ok(ff)
     }
 
-    #[inline]
-    pub fn ast(&self) -> &AstNode<'src> {
-        &self.ast
+    fn mount(&mut self, source: usize, namespace: Option<String>) {
+        let sources = self.sources;
+        let dir = sources[source]
+            .path
+            .parent()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+
+        let mut unit = Vec::with_capacity(8);
+        collect_included_sources(sources, source, &mut unit, &mut HashSet::new());
+
+        self.modules.push(Module {
+            namespace: namespace.clone(),
+            dir,
+            source,
+        });
+
+        for index in unit {
+            for (name, module_source) in &sources[index].modules {
+                let namespace = match &namespace {
+                    Some(ns) => format!("{}:{}", ns, name),
+                    None => name.clone(),
+                };
+                self.mount(*module_source, Some(namespace));
+            }
+        }
+    }
+
+    /// All top-level statements of every module, with included files spliced in where they
+    /// are included.
+    pub fn items(&self) -> Vec<Item<'_, 'src>> {
+        let mut items = Vec::with_capacity(64);
+        for module in &self.modules {
+            let mut visited = HashSet::new();
+            self.collect_items(module, module.source, &mut items, &mut visited);
+        }
+        items
+    }
+
+    fn collect_items<'a>(
+        &'a self,
+        module: &'a Module,
+        index: usize,
+        items: &mut Vec<Item<'a, 'src>>,
+        visited: &mut HashSet<usize>,
+    ) {
+        if !visited.insert(index) {
+            return;
+        }
+
+        let source = &self.sources[index];
+        let AstNode::Root(body) = &self.asts[index] else {
+            unreachable!()
+        };
+
+        let mut includes = source.includes.iter();
+        for node in body {
+            match node {
+                AstNode::Include(_) => {
+                    for included in includes.next().expect("include was loaded") {
+                        self.collect_items(module, *included, items, visited);
+                    }
+                }
+                AstNode::Module(_) => {}
+                _ => items.push(Item {
+                    source: &source.path,
+                    module,
+                    node,
+                }),
+            }
+        }
+    }
+}
+
+fn collect_included_sources(
+    sources: &[Source],
+    index: usize,
+    unit: &mut Vec<usize>,
+    visited: &mut HashSet<usize>,
+) {
+    if !visited.insert(index) {
+        return;
+    }
+    unit.push(index);
+    for included in sources[index].includes.iter().flatten() {
+        collect_included_sources(sources, *included, unit, visited);
+    }
+}
+
+/// Read a friggenfile and every file it includes or mounts as a module, recursively.
+pub fn load_sources(path: &Path) -> Result<Vec<Source>> {
+    let mut loader = SourceLoader {
+        sources: vec![],
+        canonical_paths: vec![],
+        stack: vec![],
+    };
+    loader.load(path)?;
+    Ok(loader.sources)
+}
+
+struct SourceLoader {
+    sources: Vec<Source>,
+    canonical_paths: Vec<PathBuf>,
+    stack: Vec<PathBuf>,
+}
+
+impl SourceLoader {
+    fn load(&mut self, path: &Path) -> Result<usize> {
+        let canonical_path = fs::canonicalize(path)?;
+
+        if self.stack.contains(&canonical_path) {
+            self.stack.push(canonical_path);
+            return Err(FriggenError::CyclicInclude(
+                self.stack
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect(),
+            ));
+        }
+
+        // Files included more than once are only loaded the first time
+        if let Some(index) = self
+            .canonical_paths
+            .iter()
+            .position(|p| *p == canonical_path)
+        {
+            return Ok(index);
+        }
+
+        let buf = read_file(path)?;
+        let text = String::from_utf8_lossy(&buf).to_string();
+        let source = Source {
+            path: PathBuf::from(path),
+            text,
+            includes: vec![],
+            modules: vec![],
+        };
+
+        let mut include_paths: Vec<String> = vec![];
+        let mut module_paths: Vec<(String, String)> = vec![];
+        if let AstNode::Root(body) = parse_source(&source)? {
+            for node in body {
+                match node {
+                    AstNode::Include(path) => include_paths.push(path.to_string()),
+                    AstNode::Module(module) => {
+                        module_paths.push((module.name.to_string(), module.path.to_string()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let index = self.sources.len();
+        self.sources.push(source);
+        self.canonical_paths.push(canonical_path.clone());
+        self.stack.push(canonical_path);
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        for include_path in include_paths {
+            let mut included = vec![];
+            for path in resolve_include(path, base_dir, &include_path)? {
+                included.push(self.load(&path)?);
+            }
+            self.sources[index].includes.push(included);
+        }
+
+        for (name, module_path) in module_paths {
+            let module_dir = base_dir.join(&module_path);
+            let Some(module_ff) = contains_friggenfile(&module_dir)? else {
+                return Err(FriggenError::ModuleNotFound {
+                    module: name,
+                    path: module_dir.to_string_lossy().to_string(),
+                });
+            };
+            let module_index = self.load(&module_ff)?;
+            self.sources[index].modules.push((name, module_index));
+        }
+
+        let top = self.stack.pop();
+        assert!(top.is_some());
+
+        Ok(index)
+    }
+}
+
+/// Resolve an include path relative to the directory of the including file. Glob patterns
+/// resolve to every matching file, in alphabetical order.
+fn resolve_include(referrer: &Path, base_dir: &Path, include: &str) -> Result<Vec<PathBuf>> {
+    let path = base_dir.join(include);
+
+    if include.contains(['*', '?', '[']) {
+        let pattern = path.to_string_lossy();
+        let paths = glob::glob(&pattern)
+            .map_err(anyhow::Error::from)?
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(anyhow::Error::from)?;
+        return Ok(paths.into_iter().filter(|p| p.is_file()).collect());
+    }
+
+    if !path.is_file() {
+        return Err(FriggenError::IncludeNotFound {
+            include: include.to_string(),
+            referrer: referrer.to_string_lossy().to_string(),
+        });
+    }
+    Ok(vec![path])
+}
+
+fn parse_source(source: &Source) -> Result<AstNode<'_>> {
+    parse_friggenfile(&source.text).map_err(|e| {
+        FriggenError::FriggenfileSyntaxError(Box::new(e.with_path(&source.path.to_string_lossy())))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    use crate::ast::{AstAttrArg, AstNode, AstTaskAttr};
+    use crate::error::FriggenError;
+    use crate::friggen::build_task_map;
+    use crate::friggenfile::{
+        load_sources, Backoff, DocTag, Friggenfile, Module, RetryPolicy, Task, TaskAttributes,
+        TaskDocs,
+    };
+
+    fn write(dir: &Path, name: &str, text: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        load_sources(&dir.join("friggenfile"))
+            .unwrap()
+            .iter()
+            .map(|source| {
+                let path = source.path.strip_prefix(dir).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect()
+    }
+
+    fn build_task<'a>(module: &'a Module, sources: &[&'a str], outputs: &[&'a str]) -> Task<'a> {
+        Task {
+            name: Cow::Borrowed("build"),
+            aliases: vec![],
+            order: 0,
+            group: None,
+            module,
+            source: Path::new("friggenfile"),
+            attrs: TaskAttributes {
+                sources: sources.to_vec(),
+                outputs: outputs.to_vec(),
+                ..Default::default()
+            },
+            docs: TaskDocs::default(),
+            params: vec![],
+            deps: vec![],
+            hash_bang: None,
+            script: vec![],
+        }
+    }
+
+    /// Write a file, modified the given number of seconds ago.
+    fn write_aged(dir: &Path, name: &str, age: u64) {
+        write(dir, name, name);
+        let modified = SystemTime::now() - Duration::from_secs(age);
+        File::options()
+            .write(true)
+            .open(dir.join(name))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_is_up_to_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let module = Module {
+            namespace: None,
+            dir: dir.path().to_path_buf(),
+            source: 0,
+        };
+        write_aged(dir.path(), "src/main.c", 60);
+        write_aged(dir.path(), "src/util.c", 60);
+
+        // No outputs, nothing to check
+        assert!(!build_task(&module, &["src/*.c"], &[])
+            .is_up_to_date()
+            .unwrap());
+
+        // Missing output
+        let task = build_task(&module, &["src/*.c"], &["app", "app.map"]);
+        write_aged(dir.path(), "app", 30);
+        assert!(!task.is_up_to_date().unwrap());
+
+        write_aged(dir.path(), "app.map", 30);
+        assert!(task.is_up_to_date().unwrap());
+
+        // A source newer than the oldest output
+        write_aged(dir.path(), "app.map", 90);
+        assert!(!task.is_up_to_date().unwrap());
+        write_aged(dir.path(), "app.map", 30);
+        write_aged(dir.path(), "src/util.c", 10);
+        assert!(!task.is_up_to_date().unwrap());
+
+        // Without sources, outputs that exist are always up to date
+        assert!(build_task(&module, &[], &["app"]).is_up_to_date().unwrap());
+    }
+
+    /// A root friggenfile with an `api` module in `services/api`, which has a `db` module, and a
+    /// `web` module in `web`.
+    fn modules_tree(dir: &Path) {
+        write(
+            dir,
+            "friggenfile",
+            "module api \"services/api\"\nmodule web\n\nall: api:test web:build\n  echo all\n",
+        );
+        write(
+            dir,
+            "services/api/friggenfile",
+            "module db\n\nbuild:\n  make\n\ntest: build db:migrate\n  make test\n",
+        );
+        write(
+            dir,
+            "services/api/db/friggenfile",
+            "migrate:\n  ./migrate\n",
+        );
+        write(dir, "web/friggenfile", "build:\n  npm run build\n");
+    }
+
+    #[test]
+    fn test_mount() {
+        let dir = tempfile::tempdir().unwrap();
+        modules_tree(dir.path());
+        let sources = load_sources(&dir.path().join("friggenfile")).unwrap();
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+
+        let modules: Vec<(Option<&str>, &Path)> = ff
+            .modules
+            .iter()
+            .map(|module| (module.namespace.as_deref(), module.dir.as_path()))
+            .collect();
+        assert_eq!(
+            modules,
+            [
+                (None, dir.path()),
+                (Some("api"), dir.path().join("services/api").as_path()),
+                (Some("api:db"), dir.path().join("services/api/db").as_path()),
+                (Some("web"), dir.path().join("web").as_path()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_module_qualify() {
+        let root = Module {
+            namespace: None,
+            dir: PathBuf::from("."),
+            source: 0,
+        };
+        let db = Module {
+            namespace: Some("api:db".to_string()),
+            dir: PathBuf::from("services/api/db"),
+            source: 1,
+        };
+        assert_eq!(root.qualify("build"), "build");
+        assert_eq!(root.qualify("api:test"), "api:test");
+        assert_eq!(db.qualify("migrate"), "api:db:migrate");
+    }
+
+    #[test]
+    fn test_module_working_dir() {
+        let root = Module {
+            namespace: None,
+            dir: PathBuf::from("/project"),
+            source: 0,
+        };
+        let web = Module {
+            namespace: Some("web".to_string()),
+            dir: PathBuf::from("/project/web"),
+            source: 1,
+        };
+        // The root friggenfile runs wherever `-w` says, modules always run at home
+        assert_eq!(
+            root.working_dir(Path::new("/elsewhere")),
+            Path::new("/elsewhere")
+        );
+        assert_eq!(
+            web.working_dir(Path::new("/elsewhere")),
+            Path::new("/project/web")
+        );
+    }
+
+    #[test]
+    fn test_module_deps() {
+        let dir = tempfile::tempdir().unwrap();
+        modules_tree(dir.path());
+        let sources = load_sources(&dir.path().join("friggenfile")).unwrap();
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let mut tasks = HashMap::new();
+        let mut aliases = HashMap::new();
+        build_task_map(&ff.items(), &mut tasks, &mut aliases).unwrap();
+
+        let deps = |name: &str| -> Vec<String> {
+            tasks[name]
+                .deps
+                .iter()
+                .map(|dep| dep.name.to_string())
+                .collect()
+        };
+        // Dependencies are relative to the friggenfile they're declared in
+        assert_eq!(deps("all"), ["api:test", "web:build"]);
+        assert_eq!(deps("api:test"), ["api:build", "api:db:migrate"]);
+        assert_eq!(
+            tasks["api:db:migrate"].module.dir,
+            dir.path().join("services/api/db")
+        );
+    }
+
+    #[test]
+    fn test_load_sources_glob_include() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "friggenfile", "include \"tasks/*.friggen\"\n");
+        write(dir.path(), "tasks/b.friggen", "b:\n  echo b\n");
+        write(dir.path(), "tasks/a.friggen", "a:\n  echo a\n");
+        write(dir.path(), "tasks/notes.txt", "not a friggenfile\n");
+
+        assert_eq!(
+            file_names(dir.path()),
+            ["friggenfile", "tasks/a.friggen", "tasks/b.friggen"]
+        );
+    }
+
+    #[test]
+    fn test_load_sources_included_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "friggenfile",
+            "include \"common.friggen\"\ninclude \"ci/friggenfile\"\n",
+        );
+        write(
+            dir.path(),
+            "ci/friggenfile",
+            "include \"../common.friggen\"\n",
+        );
+        write(dir.path(), "common.friggen", "lint:\n  cargo clippy\n");
+
+        assert_eq!(
+            file_names(dir.path()),
+            ["friggenfile", "common.friggen", "ci/friggenfile"]
+        );
+
+        // The tasks of a file included twice only show up once
+        let sources = load_sources(&dir.path().join("friggenfile")).unwrap();
+        let ff = Friggenfile::from_sources(&sources).unwrap();
+        let tasks = ff
+            .items()
+            .iter()
+            .filter(|item| matches!(item.node, AstNode::TaskDef(_)))
+            .count();
+        assert_eq!(tasks, 1);
+    }
+
+    #[test]
+    fn test_load_sources_cyclic_include() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "friggenfile", "include \"a.friggen\"\n");
+        write(dir.path(), "a.friggen", "include \"b.friggen\"\n");
+        write(dir.path(), "b.friggen", "include \"a.friggen\"\n");
+
+        let err = load_sources(&dir.path().join("friggenfile")).unwrap_err();
+        let FriggenError::CyclicInclude(cycle) = err else {
+            panic!("expected a cyclic include, got {:?}", err);
+        };
+        let names: Vec<&str> = cycle
+            .iter()
+            .map(|path| path.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["friggenfile", "a.friggen", "b.friggen", "a.friggen"]
+        );
+    }
+
+    fn attr<'a>(name: &'a str, args: &[(Option<&'a str>, &'a str)]) -> AstNode<'a> {
+        AstNode::TaskAttr(AstTaskAttr {
+            name,
+            args: args
+                .iter()
+                .map(|&(key, value)| AstAttrArg { key, value })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_cache_outputs_attr() {
+        let cached = |output| {
+            TaskAttributes::from_ast(
+                "build",
+                &[attr("cache", &[]), attr("outputs", &[(None, output)])],
+            )
+        };
+        assert!(cached("target/app").is_ok());
+        for output in ["/tmp/report.txt", "../report.txt", "out/../../report.txt"] {
+            assert!(matches!(
+                cached(output),
+                Err(FriggenError::InvalidTaskAttribute { attr, .. }) if attr == "outputs"
+            ));
+        }
+        let uncached = TaskAttributes::from_ast("build", &[attr("outputs", &[(None, "/tmp/x")])]);
+        assert!(uncached.is_ok());
+    }
+
+    #[test]
+    fn test_retry_attr() {
+        let attrs = TaskAttributes::from_ast("install", &[attr("retry", &[(None, "3")])]).unwrap();
+        assert_eq!(
+            attrs.retry,
+            Some(RetryPolicy {
+                attempts: 3,
+                backoff: Backoff::Fixed,
+                delay: Duration::from_secs(1),
+                codes: vec![],
+            })
+        );
+
+        let args = [
+            (None, "5"),
+            (Some("backoff"), "exponential"),
+            (Some("delay"), "500ms"),
+            (Some("codes"), "1|75"),
+        ];
+        let attrs = TaskAttributes::from_ast("install", &[attr("retry", &args)]).unwrap();
+        assert_eq!(
+            attrs.retry,
+            Some(RetryPolicy {
+                attempts: 5,
+                backoff: Backoff::Exponential,
+                delay: Duration::from_millis(500),
+                codes: vec![1, 75],
+            })
+        );
+
+        for args in [
+            &[][..],
+            &[(None, "0")],
+            &[(None, "3"), (Some("backoff"), "sometimes")],
+            &[(None, "3"), (Some("codes"), "one")],
+            &[(None, "3"), (Some("tries"), "2")],
+            &[(Some("delay"), "1s"), (None, "3")],
+        ] {
+            assert!(TaskAttributes::from_ast("install", &[attr("retry", args)]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let mut retry = RetryPolicy {
+            attempts: 4,
+            backoff: Backoff::Fixed,
+            delay: Duration::from_secs(2),
+            codes: vec![],
+        };
+        assert!(retry.should_retry(1, 1));
+        assert!(retry.should_retry(3, 1));
+        assert!(!retry.should_retry(4, 1));
+        assert!(!retry.should_retry(1, 0));
+        assert_eq!(retry.delay(3), Duration::from_secs(2));
+
+        retry.backoff = Backoff::Exponential;
+        assert_eq!(retry.delay(1), Duration::from_secs(2));
+        assert_eq!(retry.delay(3), Duration::from_secs(8));
+
+        retry.codes = vec![75];
+        assert!(retry.should_retry(1, 75));
+        assert!(!retry.should_retry(1, 1));
+    }
+
+    #[test]
+    fn test_task_docs() {
+        let docs = TaskDocs::parse(&[
+            "Deploy the app\n",
+            "to an environment.\n",
+            "\n",
+            "Builds an image first,\n",
+            "\n",
+            "\n",
+            "and then ships it.\n",
+            "\n",
+            "@example friggen deploy staging\n",
+            "@env AWS_PROFILE  required\n",
+            "@dangerous\n",
+        ]);
+        assert_eq!(docs.summary(), "Deploy the app to an environment.");
+        assert_eq!(
+            docs.description,
+            ["Builds an image first,", "", "and then ships it."]
+        );
+        assert_eq!(
+            docs.tags,
+            [
+                DocTag {
+                    name: "example",
+                    text: "friggen deploy staging"
+                },
+                DocTag {
+                    name: "env",
+                    text: "AWS_PROFILE  required"
+                },
+                DocTag {
+                    name: "dangerous",
+                    text: ""
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_task_docs_summary_only() {
+        let docs = TaskDocs::parse(&["Run a foo so hard.\n"]);
+        assert_eq!(docs.summary, ["Run a foo so hard."]);
+        assert!(docs.description.is_empty());
+        assert!(docs.tags.is_empty());
     }
 }
@@ -8,35 +8,68 @@ pub const PROJECT_ROOT_INDICATORS: &[&str] = &[".bzr", ".git", ".hg", ".svn", "_
 
 pub struct FsContext {
     pub friggenfile: PathBuf,
+    /// The directory of the root friggenfile.
+    pub root_dir: PathBuf,
+    /// The directory tasks of the root friggenfile run in: the friggenfile directory, unless
+    /// another one is given.
     pub working_dir: PathBuf,
+    /// The directory friggen was run from.
+    pub invocation_dir: PathBuf,
+}
+
+impl FsContext {
+    /// Environment variables that tell task scripts where they are.
+    pub fn env_vars(&self) -> [(&'static str, String); 2] {
+        [
+            ("FRIGGEN_ROOT", self.root_dir.to_string_lossy().to_string()),
+            (
+                "FRIGGEN_INVOCATION_DIR",
+                self.invocation_dir.to_string_lossy().to_string(),
+            ),
+        ]
+    }
 }
 
 pub fn resolve_fs_context(
     supplied_ff_path: Option<&Path>,
     supplied_wd_path: Option<&Path>,
 ) -> Result<FsContext> {
-    match (supplied_ff_path, supplied_wd_path) {
-        (Some(ff), Some(wd)) => Ok(FsContext {
-            friggenfile: PathBuf::from(ff),
-            working_dir: PathBuf::from(wd),
-        }),
-        (Some(ff), None) => Ok(FsContext {
-            friggenfile: PathBuf::from(ff),
-            working_dir: env::current_dir()?,
-        }),
-        (None, Some(wd)) => Ok(FsContext {
-            friggenfile: find_friggenfile(wd)?,
-            working_dir: PathBuf::from(wd),
-        }),
-        _ => {
-            let wd = env::current_dir()?;
-            let ff = find_friggenfile(&wd)?;
-            Ok(FsContext {
-                friggenfile: ff,
-                working_dir: wd,
-            })
-        }
-    }
+    resolve_fs_context_from(env::current_dir()?, supplied_ff_path, supplied_wd_path)
+}
+
+/// Resolve the friggenfile and directories as if friggen was run from `invocation_dir`.
+fn resolve_fs_context_from(
+    invocation_dir: PathBuf,
+    supplied_ff_path: Option<&Path>,
+    supplied_wd_path: Option<&Path>,
+) -> Result<FsContext> {
+    let friggenfile = match (supplied_ff_path, supplied_wd_path) {
+        (Some(ff), _) => PathBuf::from(ff),
+        (None, Some(wd)) => find_friggenfile(&invocation_dir.join(wd))?,
+        (None, None) => find_friggenfile(&invocation_dir)?,
+    };
+
+    let root_dir = absolute(
+        &invocation_dir,
+        friggenfile.parent().unwrap_or(Path::new("")),
+    );
+    let working_dir = match supplied_wd_path {
+        Some(wd) => absolute(&invocation_dir, wd),
+        None => root_dir.clone(),
+    };
+
+    Ok(FsContext {
+        friggenfile,
+        root_dir,
+        working_dir,
+        invocation_dir,
+    })
+}
+
+/// A path relative to `dir` made absolute, without any `..` if it exists.
+fn absolute(dir: &Path, path: &Path) -> PathBuf {
+    let path = dir.join(path);
+    fs::canonicalize(&path).unwrap_or(path)
 }
 
 fn find_friggenfile(dir: &Path) -> Result<PathBuf> {
@@ -56,7 +89,7 @@ fn find_friggenfile(dir: &Path) -> Result<PathBuf> {
 }
 
 #[inline]
-fn contains_friggenfile(dir: &Path) -> Result<Option<PathBuf>> {
+pub(crate) fn contains_friggenfile(dir: &Path) -> Result<Option<PathBuf>> {
     // Look for the friggen friggenfile, unkay?
     for name in FRIGGENFILE_NAMES {
         let path = dir.join(name);
@@ -77,3 +110,74 @@ fn is_project_root(dir: &Path) -> Result<bool> {
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::fs_context::{resolve_fs_context_from, FsContext};
+
+    /// A project with a friggenfile at its root and one in `other`, run from `sub`.
+    fn project() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::create_dir(root.join("other")).unwrap();
+        fs::write(root.join("friggenfile"), "").unwrap();
+        fs::write(root.join("other/friggenfile"), "").unwrap();
+        (dir, root)
+    }
+
+    fn resolve(root: &Path, ff: Option<&str>, wd: Option<&str>) -> FsContext {
+        resolve_fs_context_from(root.join("sub"), ff.map(Path::new), wd.map(Path::new)).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_fs_context() {
+        let (_dir, root) = project();
+
+        let ctx = resolve(&root, None, None);
+        assert_eq!(ctx.friggenfile, root.join("friggenfile"));
+        assert_eq!(ctx.root_dir, root);
+        assert_eq!(ctx.working_dir, root);
+        assert_eq!(ctx.invocation_dir, root.join("sub"));
+
+        let ctx = resolve(&root, Some("../other/friggenfile"), None);
+        assert_eq!(ctx.friggenfile, PathBuf::from("../other/friggenfile"));
+        assert_eq!(ctx.root_dir, root.join("other"));
+        assert_eq!(ctx.working_dir, root.join("other"));
+
+        let ctx = resolve(&root, None, Some("../other"));
+        assert_eq!(
+            fs::canonicalize(&ctx.friggenfile).unwrap(),
+            root.join("other/friggenfile")
+        );
+        assert_eq!(ctx.root_dir, root.join("other"));
+        assert_eq!(ctx.working_dir, root.join("other"));
+
+        let ctx = resolve(&root, Some("../friggenfile"), Some("."));
+        assert_eq!(ctx.root_dir, root);
+        assert_eq!(ctx.working_dir, root.join("sub"));
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let (_dir, root) = project();
+        let ctx = resolve(&root, None, Some("../other"));
+        assert_eq!(
+            ctx.env_vars(),
+            [
+                (
+                    "FRIGGEN_ROOT",
+                    root.join("other").to_string_lossy().to_string()
+                ),
+                (
+                    "FRIGGEN_INVOCATION_DIR",
+                    root.join("sub").to_string_lossy().to_string()
+                ),
+            ]
+        );
+    }
+}
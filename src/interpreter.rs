@@ -0,0 +1,380 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::ast::AstNode;
+use crate::error::{FriggenError, Result};
+use crate::friggenfile::{ScriptMode, Task};
+
+/// Where the script path goes in an interpreter command. Without it, the script path goes last.
+pub const SCRIPT_PLACEHOLDER: &str = "{script}";
+
+/// Built-in interpreters: name, command and script file suffix.
+const BUILTIN_INTERPRETERS: &[(&str, &str, &str)] = &[
+    ("bash", "bash", ""),
+    ("sh", "sh", ""),
+    ("zsh", "zsh", ""),
+    ("python", "python3", ".py"),
+    ("node", "node", ".js"),
+    ("ruby", "ruby", ".rb"),
+    ("perl", "perl", ".pl"),
+    ("deno", "deno run {script}", ".ts"),
+    ("go", "go run {script}", ".go"),
+    ("pwsh", "pwsh -NoProfile -File {script}", ".ps1"),
+    ("tsx", "tsx", ".ts"),
+];
+
+/// Shells that run a single command with `-c`, which is what line by line mode needs.
+const SHELLS: &[&str] = &["bash", "sh", "zsh", "dash", "ash", "ksh", "fish"];
+
+/// How to run a task script.
+#[derive(Debug, Clone)]
+pub struct Interpreter<'a> {
+    /// The program and its arguments, where `{script}` stands for the script path.
+    pub command: Vec<Cow<'a, str>>,
+    /// The suffix of the script file, for interpreters that care, like `.go`.
+    pub suffix: &'a str,
+    /// Lines to run before the script, like `set -euo pipefail`.
+    pub preamble: Option<&'static str>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// The name of the program the interpreter runs, looking past `/usr/bin/env`.
+    fn program(&self) -> Option<&str> {
+        let mut command = self.command.iter().map(|c| c.as_ref());
+        let program = command.next()?;
+        let program = if file_name(program) == "env" {
+            // Skip env options and variable assignments
+            command.find(|arg| !arg.starts_with('-') && !arg.contains('='))?
+        } else {
+            program
+        };
+        Some(file_name(program))
+    }
+
+    /// Whether the interpreter is a shell that can run a script line by line.
+    fn is_shell(&self) -> bool {
+        self.program()
+            .is_some_and(|program| SHELLS.contains(&program))
+    }
+
+    /// The lines that make a shell stop at the first failing command, for shells we know.
+    fn strict_preamble(&self) -> Option<&'static str> {
+        match self.program()? {
+            "bash" | "zsh" => Some("set -euo pipefail\n"),
+            "sh" | "dash" | "ash" | "ksh" => Some("set -eu\n"),
+            _ => None,
+        }
+    }
+}
+
+/// Built-in interpreters, along with the ones defined with `interpreter` lines.
+#[derive(Debug, Clone)]
+pub struct Interpreters<'a> {
+    entries: HashMap<&'a str, Interpreter<'a>>,
+    /// Names of built-in interpreters by the program they run, like `python3` for `python`.
+    programs: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Interpreters<'a> {
+    /// Read interpreter definitions from top-level statements. A definition replaces a built-in
+    /// interpreter or an earlier definition with the same name. The command of a definition has
+    /// to start with the program that runs the script.
+    pub fn from_ast<'n>(nodes: impl IntoIterator<Item = &'n AstNode<'a>>) -> Result<Self>
+    where
+        'a: 'n,
+    {
+        let mut entries: HashMap<&'a str, Interpreter<'a>> = HashMap::new();
+        let mut programs: HashMap<&'a str, &'a str> = HashMap::new();
+        for (name, command, suffix) in BUILTIN_INTERPRETERS {
+            let command: Vec<&'static str> = command.split_whitespace().collect();
+            programs.insert(command[0], name);
+            let command = command.into_iter().map(Cow::from).collect();
+            entries.insert(
+                name,
+                Interpreter {
+                    command,
+                    suffix,
+                    preamble: None,
+                },
+            );
+        }
+
+        for node in nodes {
+            let AstNode::Interpreter(interpreter) = node else {
+                continue;
+            };
+            let command: Vec<Cow<str>> = split_words(interpreter.command)
+                .into_iter()
+                .map(Cow::from)
+                .collect();
+            match command.first() {
+                None => {
+                    return Err(FriggenError::InvalidInterpreter {
+                        name: interpreter.name.to_string(),
+                        reason: "empty command".to_string(),
+                    })
+                }
+                Some(program) if program.contains(SCRIPT_PLACEHOLDER) => {
+                    return Err(FriggenError::InvalidInterpreter {
+                        name: interpreter.name.to_string(),
+                        reason: format!("the command can't start with {}", SCRIPT_PLACEHOLDER),
+                    })
+                }
+                Some(_) => {}
+            }
+            let suffix = interpreter.suffix.unwrap_or_default();
+            entries.insert(
+                interpreter.name,
+                Interpreter {
+                    command,
+                    suffix,
+                    preamble: None,
+                },
+            );
+        }
+
+        Ok(Self { entries, programs })
+    }
+
+    /// The interpreter that runs a task script. It's the interpreter named by `@interpreter` if
+    /// there is one, then the script shebang, then the default shell. Shebangs and the default
+    /// shell use the script file suffix of the interpreter that runs the same program.
+    ///
+    /// In strict mode, known shells stop at the first failing command. The `@strict` attribute
+    /// of the task overrides `strict`. Only shells can run a script line by line with
+    /// `@mode(lines)`.
+    pub fn for_task(
+        &self,
+        task: &Task<'a>,
+        shell: &'a str,
+        strict: bool,
+    ) -> Result<Interpreter<'a>> {
+        let mut interpreter = self.lookup(task, shell)?;
+        if task.attrs.mode == ScriptMode::Lines && !interpreter.is_shell() {
+            return Err(FriggenError::InvalidTaskAttribute {
+                task: task.name.to_string(),
+                attr: "mode".to_string(),
+                reason: format!(
+                    "only shells can run lines, not {}",
+                    interpreter.program().unwrap_or_default()
+                ),
+            });
+        }
+        if task.attrs.strict.unwrap_or(strict) {
+            interpreter.preamble = interpreter.strict_preamble();
+        }
+        Ok(interpreter)
+    }
+
+    fn lookup(&self, task: &Task<'a>, shell: &'a str) -> Result<Interpreter<'a>> {
+        if let Some(name) = task.attrs.interpreter {
+            return self.entries.get(name).cloned().ok_or_else(|| {
+                FriggenError::InvalidTaskAttribute {
+                    task: task.name.to_string(),
+                    attr: "interpreter".to_string(),
+                    reason: format!("unknown interpreter: {}", name),
+                }
+            });
+        }
+
+        let command = match &task.hash_bang {
+            Some(hash_bang) => split_env_string(hash_bang),
+            None => vec![Cow::from("/usr/bin/env"), Cow::from(shell)],
+        };
+        let mut interpreter = Interpreter {
+            command,
+            suffix: "",
+            preamble: None,
+        };
+        if let Some(program) = interpreter.program() {
+            let name = self.programs.get(program).copied().unwrap_or(program);
+            let entry = self.entries.get(name);
+            interpreter.suffix = entry.map(|entry| entry.suffix).unwrap_or_default();
+        }
+        Ok(interpreter)
+    }
+}
+
+#[inline]
+fn file_name(program: &str) -> &str {
+    Path::new(program)
+        .file_name()
+        .and_then(OsStr::to_str)
+        .unwrap_or(program)
+}
+
+/// Do the work of `env -S` in a shebang like `#!/usr/bin/env -S python3 -u`, so that the
+/// interpreter and its arguments are split the same way no matter which `env` is installed.
+/// Other shebangs are passed through as they are.
+fn split_env_string<'a>(hash_bang: &[&'a str]) -> Vec<Cow<'a, str>> {
+    if let [env, arg, rest @ ..] = hash_bang {
+        if file_name(env) == "env" {
+            let split_string = match *arg {
+                "-S" | "--split-string" => Some(rest.join(" ")),
+                arg => arg.strip_prefix("-S").map(|s| {
+                    let words: Vec<&str> = std::iter::once(s).chain(rest.iter().copied()).collect();
+                    words.join(" ")
+                }),
+            };
+            if let Some(split_string) = split_string {
+                let mut components = vec![Cow::from(*env)];
+                components.extend(split_words(&split_string).into_iter().map(Cow::from));
+                return components;
+            }
+        }
+    }
+    hash_bang.iter().map(|c| Cow::from(*c)).collect()
+}
+
+/// Split a string into words on whitespace, the way `env -S` does. Single quotes keep
+/// everything, double quotes keep everything but backslash escapes, and a backslash outside of
+/// quotes escapes the character after it.
+fn split_words(s: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                words.extend(word.take());
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                word.get_or_insert_with(String::new).extend(chars.next());
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::ast::AstNode;
+    use crate::error::FriggenError;
+    use crate::interpreter::{split_env_string, Interpreter, Interpreters};
+    use crate::parser::parse_friggenfile;
+
+    #[test]
+    fn test_invalid_interpreter() {
+        for ff in [
+            "interpreter blank \" \"\n",
+            "interpreter tabs \"\t\"\n",
+            "interpreter itself \"{script} --fast\"\n",
+        ] {
+            let ast = parse_friggenfile(ff).unwrap();
+            let AstNode::Root(body) = &ast else {
+                unreachable!()
+            };
+            assert!(matches!(
+                Interpreters::from_ast(body),
+                Err(FriggenError::InvalidInterpreter { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_split_env_string() {
+        assert_eq!(
+            split_env_string(&["/usr/bin/env", "-S", "python3", "-u"]),
+            ["/usr/bin/env", "python3", "-u"]
+        );
+        assert_eq!(
+            split_env_string(&["/usr/bin/env", "-Spython3", "-u"]),
+            ["/usr/bin/env", "python3", "-u"]
+        );
+        assert_eq!(
+            split_env_string(&[
+                "/usr/bin/env",
+                "-S",
+                "python3",
+                "-c",
+                "'print(1);",
+                "print(2)'"
+            ]),
+            ["/usr/bin/env", "python3", "-c", "print(1); print(2)"]
+        );
+        assert_eq!(
+            split_env_string(&["/usr/bin/env", "-S", "node", "\"a\\\"b\"", "c\\ d"]),
+            ["/usr/bin/env", "node", "a\"b", "c d"]
+        );
+    }
+
+    #[test]
+    fn test_program() {
+        let program = |command: &[&'static str]| {
+            let interpreter = Interpreter {
+                command: command.iter().map(|c| Cow::from(*c)).collect(),
+                suffix: "",
+                preamble: None,
+            };
+            interpreter.program().map(|p| p.to_string())
+        };
+        assert_eq!(
+            program(&["/usr/bin/python3", "-u"]).as_deref(),
+            Some("python3")
+        );
+        assert_eq!(
+            program(&["/usr/bin/env", "deno", "run"]).as_deref(),
+            Some("deno")
+        );
+        assert_eq!(
+            program(&["/usr/bin/env", "-i", "FOO=1", "node"]).as_deref(),
+            Some("node")
+        );
+        assert_eq!(program(&["/usr/bin/env"]), None);
+    }
+
+    #[test]
+    fn test_strict_preamble() {
+        let preamble = |command: &[&'static str]| {
+            let interpreter = Interpreter {
+                command: command.iter().map(|c| Cow::from(*c)).collect(),
+                suffix: "",
+                preamble: None,
+            };
+            interpreter.strict_preamble()
+        };
+        assert_eq!(
+            preamble(&["/usr/bin/env", "bash"]),
+            Some("set -euo pipefail\n")
+        );
+        assert_eq!(preamble(&["/bin/zsh"]), Some("set -euo pipefail\n"));
+        assert_eq!(preamble(&["/bin/sh", "-x"]), Some("set -eu\n"));
+        assert_eq!(preamble(&["/usr/bin/env", "python3"]), None);
+    }
+
+    #[test]
+    fn test_split_env_string_passthrough() {
+        assert_eq!(
+            split_env_string(&["/usr/bin/env", "ruby", "-w"]),
+            ["/usr/bin/env", "ruby", "-w"]
+        );
+        assert_eq!(
+            split_env_string(&["/usr/bin/node", "--max-old-space-size=4096"]),
+            ["/usr/bin/node", "--max-old-space-size=4096"]
+        );
+        assert_eq!(
+            split_env_string(&["/usr/bin/python3", "-S"]),
+            ["/usr/bin/python3", "-S"]
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use std::fs;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, BufRead, Read, Write};
 use std::path::Path;
 
 use anyhow::Result;
@@ -12,3 +12,52 @@ pub fn read_file(path: &Path) -> Result<Vec<u8>> {
     f.read_exact(&mut buffer)?;
     Ok(buffer)
 }
+
+/// Ask a yes/no question on the terminal. Anything but a yes is a no.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Read the variables assigned in a `.env` style file.
+pub fn read_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let vars = dotenvy::from_path_iter(path)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(vars)
+}
+
+/// Copy a file, or a directory and everything in it, creating parent directories as needed.
+pub fn copy_recursive(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// The total size of a file, or of every file in a directory.
+pub fn disk_size(path: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += disk_size(&entry?.path())?;
+    }
+    Ok(size)
+}
@@ -1,12 +1,13 @@
 use clap::Parser;
-use dotenv::dotenv;
 
 use crate::args::Args;
 use crate::cli::Cli;
 
 mod args;
 mod ast;
+mod cache;
 mod cli;
+mod duration;
 mod error;
 mod friggen;
 mod ioutil;
@@ -14,12 +15,13 @@ mod logging;
 
 mod friggenfile;
 mod fs_context;
+mod interpreter;
 mod parser;
 mod print;
 mod shell;
+mod vars;
 
 fn main() {
-    dotenv().ok();
     logging::init();
 
     let args = Args::parse();
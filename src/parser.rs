@@ -3,13 +3,16 @@ use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
 
-use crate::ast::{AstNode, AstTaskDef, AstTaskDep, AstTaskHeader, AstTaskScript, AstVarAssignment};
+use crate::ast::{
+    AstAttrArg, AstInterpreter, AstModule, AstNode, AstSetting, AstTaskAttr, AstTaskDef,
+    AstTaskDep, AstTaskHeader, AstTaskParam, AstTaskScript, AstVarAssignment,
+};
 
 #[derive(Parser)]
 #[grammar = "friggenfile.pest"]
 struct FriggenfileParser;
 
-pub fn parse_friggenfile(buf: &str) -> Result<AstNode, Box<Error<Rule>>> {
+pub fn parse_friggenfile(buf: &str) -> Result<AstNode<'_>, Box<Error<Rule>>> {
     let friggenfile = match FriggenfileParser::parse(Rule::friggenfile, buf) {
         Ok(mut ff) => ff.next().unwrap(),
         Err(e) => return Err(Box::new(e)),
@@ -21,9 +24,42 @@ pub fn parse_friggenfile(buf: &str) -> Result<AstNode, Box<Error<Rule>>> {
 fn parse_ast(pair: Pair<Rule>) -> AstNode {
     match pair.as_rule() {
         Rule::root => AstNode::Root(pair.into_inner().map(parse_ast).collect()),
+        Rule::include => {
+            let path = pair.into_inner().next().unwrap().as_str();
+            AstNode::Include(path)
+        }
+        Rule::setting => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str();
+            let value = pairs.next().unwrap().as_str();
+            AstNode::Setting(AstSetting { name, value })
+        }
+        Rule::group_heading => {
+            let name = pair.into_inner().next().unwrap().as_str().trim_end();
+            AstNode::Group(name)
+        }
+        Rule::interpreter => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str();
+            let command = pairs.next().unwrap().as_str();
+            let suffix = pairs.next().map(|p| p.as_str());
+            AstNode::Interpreter(AstInterpreter {
+                name,
+                command,
+                suffix,
+            })
+        }
+        Rule::module => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str();
+            // The path defaults to a directory named after the module
+            let path = pairs.next().map(|p| p.as_str()).unwrap_or(name);
+            AstNode::Module(AstModule { name, path })
+        }
         Rule::var_assignment => {
             let mut pairs = pair.into_inner();
             let name = pairs.next().unwrap().as_str();
+            let conditional = pairs.next().unwrap().as_str() == "?=";
             let value = if let Some(next) = pairs.next() {
                 parse_ast(next)
             } else {
@@ -31,44 +67,94 @@ fn parse_ast(pair: Pair<Rule>) -> AstNode {
             };
             AstNode::VarAssignment(AstVarAssignment {
                 name,
+                conditional,
                 value: Box::new(value),
             })
         }
-        Rule::plain_value
-        | Rule::single_quoted_value
-        | Rule::double_quoted_value
-        | Rule::triple_quoted_value => {
+        Rule::single_quoted_value => {
             let value = pair.as_str();
             AstNode::VarValue(value)
         }
+        Rule::plain_value | Rule::triple_quoted_value => {
+            let value = pair.as_str();
+            AstNode::InterpolatedValue(value)
+        }
+        Rule::double_quoted_value => {
+            let value = pair.as_str();
+            AstNode::EscapedValue(value)
+        }
         Rule::command_sub_command => {
             let command = pair.as_str();
             AstNode::CommandSubstitution(command)
         }
         Rule::task_def => {
-            let mut pairs = pair.into_inner();
-            let docs = if pairs.len() == 3 {
-                Some(Box::new(parse_ast(pairs.next().unwrap())))
-            } else {
-                None
-            };
-            let header = parse_ast(pairs.next().unwrap());
-            let script = parse_ast(pairs.next().unwrap());
+            let mut docs = None;
+            let mut attrs = vec![];
+            let mut header = None;
+            let mut script = None;
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::task_docs => docs = Some(Box::new(parse_ast(pair))),
+                    Rule::task_attrs => attrs = pair.into_inner().map(parse_ast).collect(),
+                    Rule::task_header => header = Some(Box::new(parse_ast(pair))),
+                    Rule::task_script => script = Some(Box::new(parse_ast(pair))),
+                    _ => unreachable!(),
+                }
+            }
             AstNode::TaskDef(AstTaskDef {
                 docs,
-                header: Box::new(header),
-                script: Box::new(script),
+                attrs,
+                header: header.unwrap(),
+                script: script.unwrap(),
             })
         }
+        Rule::task_attr => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str();
+            let args = pairs
+                .map(|arg| {
+                    let mut pairs = arg.into_inner();
+                    let first = pairs.next().unwrap();
+                    match pairs.next() {
+                        Some(value) => AstAttrArg {
+                            key: Some(first.as_str()),
+                            value: value.as_str(),
+                        },
+                        None => AstAttrArg {
+                            key: None,
+                            value: first.as_str(),
+                        },
+                    }
+                })
+                .collect();
+            AstNode::TaskAttr(AstTaskAttr { name, args })
+        }
         Rule::task_header => {
             let mut pairs = pair.into_inner();
             let name = pairs.next().unwrap().as_str();
-            let deps = if let Some(pair) = pairs.next() {
-                pair.into_inner().map(parse_ast).collect()
-            } else {
-                vec![]
-            };
-            AstNode::TaskHeader(AstTaskHeader { name, deps })
+            let mut aliases = vec![];
+            let mut params = vec![];
+            let mut deps = vec![];
+            for pair in pairs {
+                match pair.as_rule() {
+                    Rule::task_alias => aliases.push(pair.as_str()),
+                    Rule::task_param => params.push(parse_ast(pair)),
+                    Rule::task_dep_list => deps = pair.into_inner().map(parse_ast).collect(),
+                    _ => unreachable!(),
+                }
+            }
+            AstNode::TaskHeader(AstTaskHeader {
+                name,
+                aliases,
+                params,
+                deps,
+            })
+        }
+        Rule::task_param => {
+            let mut pairs = pair.into_inner();
+            let name = pairs.next().unwrap().as_str();
+            let default = pairs.next().map(|p| p.as_str());
+            AstNode::TaskParam(AstTaskParam { name, default })
         }
         Rule::task_docs => AstNode::TaskDocs(
             pair.into_inner()
@@ -143,7 +229,10 @@ fn leading_whitespace(line: &str) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use crate::ast::{AstNode, AstTaskDef, AstTaskHeader, AstTaskScript, AstVarAssignment};
+    use crate::ast::{
+        AstAttrArg, AstInterpreter, AstModule, AstNode, AstSetting, AstTaskAttr, AstTaskDef,
+        AstTaskDep, AstTaskHeader, AstTaskParam, AstTaskScript, AstVarAssignment,
+    };
     use crate::parser::parse_friggenfile;
 
     #[test]
@@ -176,6 +265,193 @@ mod tests {
         assert_eq!(ast, AstNode::Root(vec![]));
     }
 
+    #[test]
+    fn test_include() {
+        let ff = r#"
+include "ci/friggenfile.ci"
+include "tasks/*.friggen"
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![
+                AstNode::Include("ci/friggenfile.ci"),
+                AstNode::Include("tasks/*.friggen"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_include_var_name() {
+        let ff = r#"
+include = yes
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
+                name: "include",
+                conditional: false,
+                value: Box::new(AstNode::InterpolatedValue("yes")),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_module() {
+        let ff = r#"
+module api "services/api"
+module web
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![
+                AstNode::Module(AstModule {
+                    name: "api",
+                    path: "services/api",
+                }),
+                AstNode::Module(AstModule {
+                    name: "web",
+                    path: "web",
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_setting() {
+        let ast = parse_friggenfile("set default dev\nset shell \"/bin/bash\"\n").unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![
+                AstNode::Setting(AstSetting {
+                    name: "default",
+                    value: "dev",
+                }),
+                AstNode::Setting(AstSetting {
+                    name: "shell",
+                    value: "/bin/bash",
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_setting_like_task() {
+        let ast = parse_friggenfile("set x y:\n  echo hi\n").unwrap();
+        let AstNode::Root(body) = ast else {
+            unreachable!()
+        };
+        assert!(matches!(body[0], AstNode::TaskDef(_)));
+    }
+
+    #[test]
+    fn test_module_like_task() {
+        let ast = parse_friggenfile("module env:\n  echo hi\n\nmodule web").unwrap();
+        let AstNode::Root(body) = ast else {
+            unreachable!()
+        };
+        assert!(matches!(body[0], AstNode::TaskDef(_)));
+        assert!(matches!(body[1], AstNode::Module(_)));
+    }
+
+    #[test]
+    fn test_interpreter() {
+        let ff = r#"
+interpreter kotlin "kotlinc -script {script}" ".kts"
+interpreter lua "lua -W"
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![
+                AstNode::Interpreter(AstInterpreter {
+                    name: "kotlin",
+                    command: "kotlinc -script {script}",
+                    suffix: Some(".kts"),
+                }),
+                AstNode::Interpreter(AstInterpreter {
+                    name: "lua",
+                    command: "lua -W",
+                    suffix: None,
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_group_heading() {
+        let ff = r#"
+### Release Stuff
+## Ship it
+ship:
+  echo ship
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![
+                AstNode::Group("Release Stuff"),
+                AstNode::TaskDef(AstTaskDef {
+                    docs: Some(Box::new(AstNode::TaskDocs(vec!["Ship it\n"]))),
+                    attrs: vec![],
+                    header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                        name: "ship",
+                        aliases: vec![],
+                        params: vec![],
+                        deps: vec![],
+                    })),
+                    script: Box::new(AstNode::TaskScript(AstTaskScript {
+                        hash_bang: None,
+                        lines: vec!["echo ship\n"],
+                    })),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_namespaced_task_deps() {
+        let ff = r#"
+ci: api:test web:build!
+  echo hi
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: None,
+                attrs: vec![],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "ci",
+                    aliases: vec![],
+                    params: vec![],
+                    deps: vec![
+                        AstNode::TaskDep(AstTaskDep {
+                            name: "api:test",
+                            run_always: false,
+                        }),
+                        AstNode::TaskDep(AstTaskDep {
+                            name: "web:build",
+                            run_always: true,
+                        }),
+                    ],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: None,
+                    lines: vec!["echo hi\n"],
+                })),
+            })])
+        );
+    }
+
     #[test]
     fn test_simple_task() {
         let ff = r#"
@@ -188,8 +464,38 @@ foo:
             ast,
             AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
                 docs: None,
+                attrs: vec![],
                 header: Box::new(AstNode::TaskHeader(AstTaskHeader {
                     name: "foo",
+                    aliases: vec![],
+                    params: vec![],
+                    deps: vec![],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: None,
+                    lines: vec!["echo hi\n"],
+                })),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_private_task() {
+        let ff = r#"
+_foo:
+  echo hi
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: None,
+                attrs: vec![],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "_foo",
+                    aliases: vec![],
+                    params: vec![],
                     deps: vec![],
                 })),
                 script: Box::new(AstNode::TaskScript(AstTaskScript {
@@ -200,6 +506,67 @@ foo:
         );
     }
 
+    #[test]
+    fn test_task_aliases() {
+        let ff = r#"
+build|b|bld: test
+  echo hi
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: None,
+                attrs: vec![],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "build",
+                    aliases: vec!["b", "bld"],
+                    params: vec![],
+                    deps: vec![AstNode::TaskDep(AstTaskDep {
+                        name: "test",
+                        run_always: false,
+                    })],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: None,
+                    lines: vec!["echo hi\n"],
+                })),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_hash_bang() {
+        let ff = r#"
+foo:
+  #! /usr/bin/env -S python3 -u -X utf8=1
+  print("hi")
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: None,
+                attrs: vec![],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "foo",
+                    aliases: vec![],
+                    params: vec![],
+                    deps: vec![],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: Some(vec!["/usr/bin/env", "-S", "python3", "-u", "-X", "utf8=1"]),
+                    lines: vec![
+                        "#! /usr/bin/env -S python3 -u -X utf8=1\n",
+                        "print(\"hi\")\n"
+                    ],
+                })),
+            })])
+        );
+    }
+
     #[test]
     fn test_two_simple_tasks() {
         let ff = r#"
@@ -216,8 +583,11 @@ bar:
             AstNode::Root(vec![
                 AstNode::TaskDef(AstTaskDef {
                     docs: None,
+                    attrs: vec![],
                     header: Box::new(AstNode::TaskHeader(AstTaskHeader {
                         name: "foo",
+                        aliases: vec![],
+                        params: vec![],
                         deps: vec![],
                     })),
                     script: Box::new(AstNode::TaskScript(AstTaskScript {
@@ -227,8 +597,11 @@ bar:
                 }),
                 AstNode::TaskDef(AstTaskDef {
                     docs: None,
+                    attrs: vec![],
                     header: Box::new(AstNode::TaskHeader(AstTaskHeader {
                         name: "bar",
+                        aliases: vec![],
+                        params: vec![],
                         deps: vec![],
                     })),
                     script: Box::new(AstNode::TaskScript(AstTaskScript {
@@ -253,8 +626,11 @@ foo:
             ast,
             AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
                 docs: Some(Box::new(AstNode::TaskDocs(vec!["foo kicks ass\n"]))),
+                attrs: vec![],
                 header: Box::new(AstNode::TaskHeader(AstTaskHeader {
                     name: "foo",
+                    aliases: vec![],
+                    params: vec![],
                     deps: vec![],
                 })),
                 script: Box::new(AstNode::TaskScript(AstTaskScript {
@@ -282,8 +658,11 @@ foo:
                     "foo kicks ass\n",
                     "no seriously\n"
                 ]))),
+                attrs: vec![],
                 header: Box::new(AstNode::TaskHeader(AstTaskHeader {
                     name: "foo",
+                    aliases: vec![],
+                    params: vec![],
                     deps: vec![],
                 })),
                 script: Box::new(AstNode::TaskScript(AstTaskScript {
@@ -312,8 +691,162 @@ foo:
                     "foo kicks ass\n",
                     "no seriously\n"
                 ]))),
+                attrs: vec![],
                 header: Box::new(AstNode::TaskHeader(AstTaskHeader {
                     name: "foo",
+                    aliases: vec![],
+                    params: vec![],
+                    deps: vec![],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: None,
+                    lines: vec!["echo hi\n"],
+                })),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_task_params() {
+        let ff = r#"
+deploy env region=us-east tag="" note='a b':
+  echo hi
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: None,
+                attrs: vec![],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "deploy",
+                    aliases: vec![],
+                    params: vec![
+                        AstNode::TaskParam(AstTaskParam {
+                            name: "env",
+                            default: None,
+                        }),
+                        AstNode::TaskParam(AstTaskParam {
+                            name: "region",
+                            default: Some("us-east"),
+                        }),
+                        AstNode::TaskParam(AstTaskParam {
+                            name: "tag",
+                            default: Some(""),
+                        }),
+                        AstNode::TaskParam(AstTaskParam {
+                            name: "note",
+                            default: Some("a b"),
+                        }),
+                    ],
+                    deps: vec![],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: None,
+                    lines: vec!["echo hi\n"],
+                })),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_task_params_with_deps() {
+        let ff = r#"
+release version: build test!
+  echo hi
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: None,
+                attrs: vec![],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "release",
+                    aliases: vec![],
+                    params: vec![AstNode::TaskParam(AstTaskParam {
+                        name: "version",
+                        default: None,
+                    })],
+                    deps: vec![
+                        AstNode::TaskDep(AstTaskDep {
+                            name: "build",
+                            run_always: false,
+                        }),
+                        AstNode::TaskDep(AstTaskDep {
+                            name: "test",
+                            run_always: true,
+                        }),
+                    ],
+                })),
+                script: Box::new(AstNode::TaskScript(AstTaskScript {
+                    hash_bang: None,
+                    lines: vec!["echo hi\n"],
+                })),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_task_attrs() {
+        let ff = r#"
+## foo kicks ass
+@private
+@dir("frontend")
+@timeout(10m)
+@retry(3, backoff = exponential, codes=1|2)
+foo:
+  echo hi
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::TaskDef(AstTaskDef {
+                docs: Some(Box::new(AstNode::TaskDocs(vec!["foo kicks ass\n"]))),
+                attrs: vec![
+                    AstNode::TaskAttr(AstTaskAttr {
+                        name: "private",
+                        args: vec![],
+                    }),
+                    AstNode::TaskAttr(AstTaskAttr {
+                        name: "dir",
+                        args: vec![AstAttrArg {
+                            key: None,
+                            value: "frontend",
+                        }],
+                    }),
+                    AstNode::TaskAttr(AstTaskAttr {
+                        name: "timeout",
+                        args: vec![AstAttrArg {
+                            key: None,
+                            value: "10m",
+                        }],
+                    }),
+                    AstNode::TaskAttr(AstTaskAttr {
+                        name: "retry",
+                        args: vec![
+                            AstAttrArg {
+                                key: None,
+                                value: "3",
+                            },
+                            AstAttrArg {
+                                key: Some("backoff"),
+                                value: "exponential",
+                            },
+                            AstAttrArg {
+                                key: Some("codes"),
+                                value: "1|2",
+                            },
+                        ],
+                    }),
+                ],
+                header: Box::new(AstNode::TaskHeader(AstTaskHeader {
+                    name: "foo",
+                    aliases: vec![],
+                    params: vec![],
                     deps: vec![],
                 })),
                 script: Box::new(AstNode::TaskScript(AstTaskScript {
@@ -335,6 +868,7 @@ foo =
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
+                conditional: false,
                 value: Box::new(AstNode::VarValue("")),
             })])
         );
@@ -351,7 +885,8 @@ foo = bar
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
-                value: Box::new(AstNode::VarValue("bar")),
+                conditional: false,
+                value: Box::new(AstNode::InterpolatedValue("bar")),
             })])
         );
     }
@@ -366,7 +901,8 @@ foo = bar"#;
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
-                value: Box::new(AstNode::VarValue("bar")),
+                conditional: false,
+                value: Box::new(AstNode::InterpolatedValue("bar")),
             })])
         );
     }
@@ -382,6 +918,7 @@ foo = 'bar'
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
+                conditional: false,
                 value: Box::new(AstNode::VarValue("bar")),
             })])
         );
@@ -398,7 +935,8 @@ foo = "bar"
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
-                value: Box::new(AstNode::VarValue("bar")),
+                conditional: false,
+                value: Box::new(AstNode::EscapedValue("bar")),
             })])
         );
     }
@@ -414,7 +952,25 @@ foo = """bar"""
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
-                value: Box::new(AstNode::VarValue("bar")),
+                conditional: false,
+                value: Box::new(AstNode::InterpolatedValue("bar")),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_var_assignment_conditional() {
+        let ff = r#"
+foo ?= bar
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
+                name: "foo",
+                conditional: true,
+                value: Box::new(AstNode::InterpolatedValue("bar")),
             })])
         );
     }
@@ -430,8 +986,62 @@ foo = $(echo "bar")
             ast,
             AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
                 name: "foo",
+                conditional: false,
                 value: Box::new(AstNode::CommandSubstitution("echo \"bar\"")),
             })])
         );
     }
+
+    #[test]
+    fn test_var_assignment_double_quote_escapes() {
+        let ff = r#"
+foo = "say \"hi\" \\ \${bar}"
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
+                name: "foo",
+                conditional: false,
+                value: Box::new(AstNode::EscapedValue(r#"say \"hi\" \\ \${bar}"#)),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_var_assignment_command_substitution_nested() {
+        let ff = r#"
+foo = $(echo $(date +%s) (sub))
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
+                name: "foo",
+                conditional: false,
+                value: Box::new(AstNode::CommandSubstitution("echo $(date +%s) (sub)")),
+            })])
+        );
+    }
+
+    #[test]
+    fn test_var_assignment_command_substitution_quoted() {
+        let ff = r#"
+foo = $(awk '{print ($1)}' "(" \) ")\"")
+"#;
+
+        let ast = parse_friggenfile(ff).unwrap();
+        assert_eq!(
+            ast,
+            AstNode::Root(vec![AstNode::VarAssignment(AstVarAssignment {
+                name: "foo",
+                conditional: false,
+                value: Box::new(AstNode::CommandSubstitution(
+                    r#"awk '{print ($1)}' "(" \) ")\"""#
+                )),
+            })])
+        );
+    }
 }
@@ -6,6 +6,7 @@ use colored::{ColoredString, Colorize};
 pub enum PrintTheme {
     ThisFriggenKicksAss,
     ThisFriggenSucks,
+    NothingToFriggenDo,
 }
 
 impl PrintTheme {
@@ -14,6 +15,7 @@ impl PrintTheme {
         match self {
             Self::ThisFriggenKicksAss => text.bright_blue().bold(),
             Self::ThisFriggenSucks => text.yellow().bold(),
+            Self::NothingToFriggenDo => text.bright_cyan().bold(),
         }
     }
 
@@ -22,6 +24,7 @@ impl PrintTheme {
         match self {
             Self::ThisFriggenKicksAss => text.green(),
             Self::ThisFriggenSucks => text.red(),
+            Self::NothingToFriggenDo => text.blue(),
         }
     }
 }
@@ -77,6 +80,15 @@ impl OutputPrinter {
         );
     }
 
+    #[inline]
+    pub fn print_command(&self, command: &str) {
+        if self.quiet {
+            return;
+        }
+
+        println!("{} {}", self.theme.bg("○──»"), self.theme.fg(command));
+    }
+
     #[inline]
     pub fn print_section_header(&self, title: &str) {
         if self.quiet {
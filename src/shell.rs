@@ -1,24 +1,41 @@
-use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{BufWriter, Write};
+use std::io::{BufWriter, Read, Write};
 use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::{fs, io};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
 
-use tempfile::NamedTempFile;
+use tempfile::Builder;
 
 use crate::error::Result;
+use crate::interpreter::{Interpreter, SCRIPT_PLACEHOLDER};
 
-const SCRIPT_DIR: &str = "~/.cache/friggen";
+pub const SCRIPT_DIR: &str = "~/.cache/friggen";
 const SCRIPT_DIR_MODE: u32 = 0o750;
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long a timed out command gets to clean up after SIGTERM, before SIGKILL.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+/// Signals that would take friggen down without the process group of a timed command.
+const FORWARDED_SIGNALS: [libc::c_int; 3] = [libc::SIGHUP, libc::SIGINT, libc::SIGTERM];
+
+/// The last of `FORWARDED_SIGNALS` friggen got while a timed command ran, or 0.
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
 
 pub fn eval_shell_command(
     shell: &str,
     cmd: &str,
+    dir: Option<&Path>,
     env_vars: &HashMap<&str, &str>,
 ) -> Result<String> {
-    let out = Command::new("/usr/bin/env")
+    let mut command = Command::new("/usr/bin/env");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    let out = command
         .arg(shell)
         .arg("-c")
         .arg(cmd)
@@ -42,11 +59,14 @@ pub fn eval_shell_command(
 }
 
 pub fn run_shell_script(
-    hash_bang: &[&str],
+    interpreter: &Interpreter,
     lines: &[&str],
-    env_vars: &HashMap<&str, &str>,
-    other_vars: &HashMap<&str, Cow<'_, str>>,
-) -> Result<i32> {
+    script_args: &[&str],
+    dir: &Path,
+    env: &HashMap<&str, &str>,
+    timeout: Option<Duration>,
+    stdout: Option<&mut Vec<u8>>,
+) -> Result<Option<i32>> {
     let script_dir = PathBuf::from(shellexpand::tilde(SCRIPT_DIR).to_string());
     if let Ok(dir_meta) = script_dir.metadata() {
         dir_meta.permissions().set_mode(SCRIPT_DIR_MODE);
@@ -58,31 +78,486 @@ pub fn run_shell_script(
             .set_mode(SCRIPT_DIR_MODE);
     }
 
-    let script_file = &NamedTempFile::new_in(script_dir)?;
+    let script_file = &Builder::new()
+        .prefix("friggen-")
+        .suffix(interpreter.suffix)
+        .tempfile_in(script_dir)?;
     let script_path = script_file.path();
 
     let size_guess = (lines.len() * 128).max(2048);
     let mut writer = BufWriter::with_capacity(size_guess, script_file);
+    let mut lines = lines.iter().peekable();
+    // Keep a shebang as the first line
+    if let Some(line) = lines.next_if(|line| line.starts_with("#!")) {
+        writer.write_all(line.as_bytes())?;
+    }
+    if let Some(preamble) = interpreter.preamble {
+        writer.write_all(preamble.as_bytes())?;
+    }
     for line in lines {
         writer.write_all(line.as_bytes())?;
     }
     writer.flush()?;
 
-    let mut hash_bang_components = hash_bang.iter();
-    let mut child = Command::new(hash_bang_components.next().unwrap());
-    for arg in hash_bang_components {
-        child.arg(arg);
+    let mut components = interpreter.command.iter();
+    let mut child = Command::new(components.next().unwrap().as_ref());
+    let mut placed_script = false;
+    for arg in components {
+        if arg.contains(SCRIPT_PLACEHOLDER) {
+            let script_path = script_path.to_string_lossy();
+            child.arg(arg.replace(SCRIPT_PLACEHOLDER, &script_path));
+            placed_script = true;
+        } else {
+            child.arg(arg.as_ref());
+        }
+    }
+    if !placed_script {
+        child.arg(script_path);
     }
+    child.args(script_args);
 
-    let code = child
-        .envs(env_vars.iter())
-        .envs(other_vars.iter().map(|e| (e.0, e.1.as_ref())))
-        .arg(script_path)
-        .stdout(Stdio::inherit())
+    run_command(child, dir, env, timeout, stdout)
+}
+
+/// A command of a task script run line by line, make style.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptCommand {
+    pub command: String,
+    /// `@`: Don't echo the command.
+    pub echo: bool,
+    /// `-`: Keep going if the command fails.
+    pub ignore_failure: bool,
+}
+
+/// Split task script lines into commands. A line ending with `\` continues on the next line,
+/// and `@` and `-` at the start of a command change how it's run. Blank lines and comments
+/// are skipped.
+pub fn split_commands(lines: &[&str]) -> Vec<ScriptCommand> {
+    let mut commands = vec![];
+    let mut command = String::new();
+    for line in lines {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if let Some(continued) = line.strip_suffix('\\') {
+            // The shell takes care of the escaped newline
+            command.push_str(continued);
+            command.push_str("\\\n");
+            continue;
+        }
+        command.push_str(line);
+
+        let mut text = command.trim_start();
+        let mut echo = true;
+        let mut ignore_failure = false;
+        loop {
+            if let Some(rest) = text.strip_prefix('@') {
+                echo = false;
+                text = rest.trim_start();
+            } else if let Some(rest) = text.strip_prefix('-') {
+                ignore_failure = true;
+                text = rest.trim_start();
+            } else {
+                break;
+            }
+        }
+
+        if !text.is_empty() && !text.starts_with('#') {
+            commands.push(ScriptCommand {
+                command: text.to_string(),
+                echo,
+                ignore_failure,
+            });
+        }
+        command.clear();
+    }
+    commands
+}
+
+/// Run a single command of a task script with the interpreter, like `bash -c <command>`.
+pub fn run_shell_command(
+    interpreter: &Interpreter,
+    command: &str,
+    script_args: &[&str],
+    dir: &Path,
+    env: &HashMap<&str, &str>,
+    timeout: Option<Duration>,
+    stdout: Option<&mut Vec<u8>>,
+) -> Result<Option<i32>> {
+    let mut components = interpreter
+        .command
+        .iter()
+        .filter(|arg| !arg.contains(SCRIPT_PLACEHOLDER));
+    let mut child = Command::new(components.next().unwrap().as_ref());
+    for arg in components {
+        child.arg(arg.as_ref());
+    }
+
+    let command = match interpreter.preamble {
+        Some(preamble) => format!("{}{}", preamble, command),
+        None => command.to_string(),
+    };
+    // The script arguments start at `$1`, like they do for a script
+    child
+        .arg("-c")
+        .arg(command)
+        .arg("friggen")
+        .args(script_args);
+
+    run_command(child, dir, env, timeout, stdout)
+}
+
+/// Run a command to completion, or until it times out. With `stdout`, the output of the command
+/// is collected as it's printed. Returns the exit code of the command, `-1` if a signal killed
+/// it, or `None` if it timed out.
+///
+/// A command with a timeout runs in a process group of its own, so that everything it starts
+/// can be stopped along with it. The group gets the terminal for as long as it runs, so that
+/// Ctrl-C still reaches it and it can read from the terminal. If friggen gets SIGHUP, SIGINT or
+/// SIGTERM in the meantime, the group is stopped before friggen goes down with the signal.
+fn run_command(
+    mut command: Command,
+    dir: &Path,
+    env: &HashMap<&str, &str>,
+    timeout: Option<Duration>,
+    stdout: Option<&mut Vec<u8>>,
+) -> Result<Option<i32>> {
+    command.current_dir(dir);
+    let terminal = timeout.and_then(|_| foreground_terminal());
+    if timeout.is_some() {
+        command.process_group(0);
+    }
+    if let Some(fd) = terminal {
+        // SAFETY: the closure only makes async-signal-safe calls
+        unsafe {
+            command.pre_exec(move || {
+                set_foreground(fd, libc::getpid());
+                Ok(())
+            });
+        }
+    }
+    // Hand the terminal back however this goes
+    let foreground = terminal.map(ForegroundGuard);
+    let signals = timeout.map(|_| SignalGuard::install());
+
+    let mut child = command
+        .envs(env.iter())
+        .stdout(if stdout.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        })
         .stderr(Stdio::inherit())
-        .spawn()?
-        .wait()?
-        .code();
+        .spawn()?;
+    if let Some(fd) = terminal {
+        // Also from this side, so that it doesn't matter which process gets there first
+        set_foreground(fd, child.id() as libc::pid_t);
+    }
+    let tee = child
+        .stdout
+        .take()
+        .map(|out| thread::spawn(move || tee_stdout(out)));
+
+    let wait = match timeout {
+        Some(timeout) => wait_timeout(&mut child, timeout)?,
+        None => Wait::Exited(child.wait()?),
+    };
+
+    let code = match wait {
+        Wait::Exited(status) => Some(status.code().unwrap_or(-1)),
+        Wait::TimedOut => {
+            terminate_group(&mut child)?;
+            None
+        }
+        Wait::Signaled(signal) => {
+            terminate_group(&mut child)?;
+            drop(foreground);
+            drop(signals);
+            // Go down the way the signal meant to take friggen down
+            // SAFETY: raise has no memory safety requirements
+            unsafe { libc::raise(signal) };
+            return Err(anyhow::Error::msg(format!("got signal {}", signal)).into());
+        }
+    };
+
+    if let (Some(tee), Some(stdout)) = (tee, stdout) {
+        let captured = tee.join().expect("stdout thread panicked")?;
+        stdout.extend(captured);
+    }
+
+    Ok(code)
+}
+
+/// Stop a child process and everything else in its process group: SIGTERM first, then SIGKILL
+/// for whatever is still around after a grace period.
+fn terminate_group(child: &mut Child) -> Result<()> {
+    let group = child.id() as libc::pid_t;
+    signal_group(group, libc::SIGTERM);
+    // A stopped process doesn't get to handle SIGTERM until it's continued
+    signal_group(group, libc::SIGCONT);
+
+    let deadline = Instant::now() + KILL_GRACE_PERIOD;
+    loop {
+        // Reap the child, so that it doesn't keep the group around as a zombie
+        child.try_wait()?;
+        if !signal_group(group, 0) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+
+    log::debug!("process group {} still running, killing it", group);
+    signal_group(group, libc::SIGKILL);
+    child.wait()?;
+    Ok(())
+}
+
+/// Send a signal to every process in a process group. Returns false if the group is gone.
+fn signal_group(group: libc::pid_t, signal: libc::c_int) -> bool {
+    // SAFETY: kill has no memory safety requirements
+    unsafe { libc::kill(-group, signal) == 0 }
+}
 
-    Ok(code.unwrap_or(-1))
+/// The terminal friggen is in the foreground of, if any.
+fn foreground_terminal() -> Option<libc::c_int> {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .find(|&fd| {
+            // SAFETY: isatty, tcgetpgrp and getpgrp have no memory safety requirements
+            unsafe { libc::isatty(fd) == 1 && libc::tcgetpgrp(fd) == libc::getpgrp() }
+        })
+}
+
+/// Make a process group the foreground process group of a terminal. Only makes
+/// async-signal-safe calls, so that a child can call it before exec.
+fn set_foreground(fd: libc::c_int, group: libc::pid_t) {
+    // SAFETY: signal and tcsetpgrp have no memory safety requirements
+    unsafe {
+        // Changing the foreground group from the background stops the process with SIGTTOU
+        let handler = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(fd, group);
+        libc::signal(libc::SIGTTOU, handler);
+    }
+}
+
+/// Catches `FORWARDED_SIGNALS` while it lives, to be handled by `wait_timeout`. Signals that
+/// were ignored stay ignored.
+struct SignalGuard {
+    previous: Vec<(libc::c_int, libc::sighandler_t)>,
+}
+
+impl SignalGuard {
+    fn install() -> Self {
+        RECEIVED_SIGNAL.store(0, Ordering::SeqCst);
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        let mut previous = vec![];
+        for signal in FORWARDED_SIGNALS {
+            // SAFETY: the handler only stores to an atomic
+            let old = unsafe { libc::signal(signal, handler) };
+            if old == libc::SIG_IGN {
+                // SAFETY: see above
+                unsafe { libc::signal(signal, libc::SIG_IGN) };
+            }
+            previous.push((signal, old));
+        }
+        Self { previous }
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        for &(signal, handler) in &self.previous {
+            // SAFETY: the handler was installed before
+            unsafe { libc::signal(signal, handler) };
+        }
+    }
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Gives the terminal back to the process group of friggen when dropped.
+struct ForegroundGuard(libc::c_int);
+
+impl Drop for ForegroundGuard {
+    fn drop(&mut self) {
+        // SAFETY: getpgrp has no memory safety requirements
+        set_foreground(self.0, unsafe { libc::getpgrp() });
+    }
+}
+
+/// Copy the output of a command to stdout, keeping a copy.
+fn tee_stdout(mut out: impl Read) -> io::Result<Vec<u8>> {
+    let mut captured = vec![];
+    let mut buffer = [0; 8192];
+    let mut stdout = io::stdout();
+    loop {
+        let n = match out.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        stdout.write_all(&buffer[..n])?;
+        stdout.flush()?;
+        captured.extend_from_slice(&buffer[..n]);
+    }
+    Ok(captured)
+}
+
+/// How waiting for a command ended.
+enum Wait {
+    Exited(ExitStatus),
+    TimedOut,
+    /// friggen got one of `FORWARDED_SIGNALS`.
+    Signaled(libc::c_int),
+}
+
+/// Wait for a child process to exit, giving up after a timeout or when friggen gets a signal.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<Wait> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Wait::Exited(status));
+        }
+        let signal = RECEIVED_SIGNAL.load(Ordering::SeqCst);
+        if signal != 0 {
+            return Ok(Wait::Signaled(signal));
+        }
+        if Instant::now() >= deadline {
+            return Ok(Wait::TimedOut);
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+    use std::{env, fs, thread};
+
+    use crate::shell::{run_command, split_commands, ScriptCommand};
+
+    fn command(command: &str, echo: bool, ignore_failure: bool) -> ScriptCommand {
+        ScriptCommand {
+            command: command.to_string(),
+            echo,
+            ignore_failure,
+        }
+    }
+
+    #[test]
+    fn test_split_commands() {
+        let lines = [
+            "echo hi\n",
+            "\n",
+            "# a comment\n",
+            "@echo quiet\n",
+            "-false\n",
+            "@- rm nope\n",
+            "cargo build \\\n",
+            "  --release\n",
+        ];
+        assert_eq!(
+            split_commands(&lines),
+            [
+                command("echo hi", true, false),
+                command("echo quiet", false, false),
+                command("false", true, true),
+                command("rm nope", false, true),
+                command("cargo build \\\n  --release", true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_command_timeout() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("sleep 10 & sleep 10");
+        let start = Instant::now();
+        let code = run_command(
+            command,
+            Path::new("."),
+            &HashMap::new(),
+            Some(Duration::from_millis(100)),
+            None,
+        )
+        .unwrap();
+        assert_eq!(code, None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("exit 3");
+        let code = run_command(
+            command,
+            Path::new("."),
+            &HashMap::new(),
+            Some(Duration::from_secs(5)),
+            None,
+        );
+        assert_eq!(code.unwrap(), Some(3));
+    }
+
+    /// Set to make `test_run_command_parent_killed` play the friggen that gets killed. The
+    /// value is the file the pid of the timed command goes to.
+    const KILLED_PARENT_VAR: &str = "FRIGGEN_TEST_KILLED_PARENT";
+
+    #[test]
+    fn test_run_command_parent_killed() {
+        if let Ok(pid_file) = env::var(KILLED_PARENT_VAR) {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(format!(
+                "echo $$ > {}.tmp && mv {0}.tmp {0} && exec sleep 30",
+                pid_file
+            ));
+            run_command(
+                command,
+                Path::new("."),
+                &HashMap::new(),
+                Some(Duration::from_secs(60)),
+                None,
+            )
+            .unwrap();
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let mut parent = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "shell::tests::test_run_command_parent_killed"])
+            .env(KILLED_PARENT_VAR, &pid_file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !pid_file.exists() {
+            assert!(Instant::now() < deadline, "timed command never started");
+            thread::sleep(Duration::from_millis(20));
+        }
+        let pid: libc::pid_t = fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+
+        // SAFETY: kill has no memory safety requirements
+        unsafe { libc::kill(parent.id() as libc::pid_t, libc::SIGTERM) };
+        let status = parent.wait().unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+        // SAFETY: see above
+        assert_ne!(
+            unsafe { libc::kill(pid, 0) },
+            0,
+            "timed command outlived friggen"
+        );
+    }
 }
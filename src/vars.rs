@@ -0,0 +1,335 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use crate::ast::{AstNode, AstVarAssignment};
+use crate::error::{FriggenError, Result};
+use crate::shell::eval_shell_command;
+
+/// Evaluate every variable assigned in a friggenfile.
+///
+/// Variables are evaluated in the order they are assigned, except that `${name}` references
+/// in a value evaluate the referenced variable first. This means a command substitution can see
+/// every variable assigned above it, and a reference can point anywhere in the friggenfile.
+///
+/// The value of a variable is taken from the first of these that sets it:
+///
+/// 1. `-e NAME=VALUE`
+/// 2. friggenfile `NAME = VALUE`
+/// 3. the process environment, then `env_file_vars` from env files
+/// 4. friggenfile `NAME ?= VALUE`
+///
+/// `=` beats the environment, so that whatever happens to be exported can't change a friggenfile
+/// that didn't ask for it. That's what `?=` is for. `-e` is given for a single run, so it beats
+/// both.
+pub fn resolve_vars<'src>(
+    nodes: &[&'src AstNode<'src>],
+    shell: &str,
+    dir: Option<&Path>,
+    env_vars: &HashMap<&str, &str>,
+    env_file_vars: &HashMap<&str, &str>,
+) -> Result<HashMap<&'src str, Cow<'src, str>>> {
+    let mut resolver = VarResolver {
+        decls: HashMap::new(),
+        order: vec![],
+        shell,
+        dir,
+        env_vars,
+        env_file_vars,
+        vars: HashMap::new(),
+        stack: vec![],
+    };
+    for node in nodes {
+        resolver.collect_decls(node);
+    }
+
+    for name in resolver.order.clone() {
+        resolver.resolve(name)?;
+    }
+
+    Ok(resolver.vars)
+}
+
+struct VarResolver<'a, 'src> {
+    decls: HashMap<&'src str, &'src AstVarAssignment<'src>>,
+    order: Vec<&'src str>,
+    shell: &'a str,
+    dir: Option<&'a Path>,
+    env_vars: &'a HashMap<&'a str, &'a str>,
+    env_file_vars: &'a HashMap<&'a str, &'a str>,
+    vars: HashMap<&'src str, Cow<'src, str>>,
+    stack: Vec<&'src str>,
+}
+
+impl<'a, 'src> VarResolver<'a, 'src> {
+    fn collect_decls(&mut self, el: &'src AstNode<'src>) {
+        match el {
+            AstNode::Root(body) => {
+                for el in body {
+                    self.collect_decls(el);
+                }
+            }
+            AstNode::VarAssignment(var) => {
+                // The last assignment of a variable wins
+                let prev = self.decls.insert(var.name, var);
+                if prev.is_none() {
+                    self.order.push(var.name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve(&mut self, name: &'src str) -> Result<()> {
+        if self.vars.contains_key(name) {
+            return Ok(());
+        }
+
+        if self.stack.contains(&name) {
+            self.stack.push(name);
+            return Err(FriggenError::CyclicVariableReference(
+                self.stack.iter().map(|name| name.to_string()).collect(),
+            ));
+        }
+
+        self.stack.push(name);
+
+        let var = self.decls[name];
+        let value = if let Some(value) = self.env_vars.get(name) {
+            Cow::from(value.to_string())
+        } else if let Some(value) = var.conditional.then(|| self.environment(name)).flatten() {
+            Cow::from(value)
+        } else {
+            self.eval(var)?
+        };
+
+        let top = self.stack.pop();
+        assert!(top.is_some());
+
+        self.vars.insert(name, value);
+        Ok(())
+    }
+
+    fn eval(&mut self, var: &'src AstVarAssignment<'src>) -> Result<Cow<'src, str>> {
+        let value = match var.value.as_ref() {
+            AstNode::VarValue(value) => Cow::from(*value),
+            AstNode::InterpolatedValue(value) => self.interpolate(var.name, value, false)?,
+            AstNode::EscapedValue(value) => self.interpolate(var.name, value, true)?,
+            AstNode::CommandSubstitution(command) => {
+                let mut env_vars: HashMap<&str, &str> = self.env_file_vars.clone();
+                env_vars.extend(self.vars.iter().map(|(k, v)| (*k, v.as_ref())));
+                env_vars.extend(self.env_vars.iter());
+
+                let output = eval_shell_command(self.shell, command, self.dir, &env_vars)?;
+                // Mimic shell behaviour of removing trailing newlines in command substitution
+                let output = output.trim_end_matches(['\r', '\n']).to_string();
+                Cow::from(output)
+            }
+            _ => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    /// A variable from the environment friggen runs in, or else from an env file.
+    fn environment(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .or_else(|| self.env_file_vars.get(name).map(|value| value.to_string()))
+    }
+
+    /// Replace `${name}` references in a value with the value of the named friggenfile variable,
+    /// falling back to the environment. With `escapes`, backslash escapes are replaced too, and
+    /// `\$` keeps a `$` from starting a reference.
+    fn interpolate(
+        &mut self,
+        referrer: &'src str,
+        value: &'src str,
+        escapes: bool,
+    ) -> Result<Cow<'src, str>> {
+        let has_escapes = escapes && value.contains('\\');
+        if !has_escapes && !value.contains("${") {
+            return Ok(Cow::from(value));
+        }
+
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find(|c| c == '$' || (escapes && c == '\\')) {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if let Some(escaped) = rest.strip_prefix('\\') {
+                let mut chars = escaped.chars();
+                match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c @ ('"' | '\\' | '$')) => out.push(c),
+                    // Unknown escapes are kept as written
+                    Some(c) => {
+                        out.push('\\');
+                        out.push(c);
+                    }
+                    None => out.push('\\'),
+                }
+                rest = chars.as_str();
+            } else if let Some(reference) = rest.strip_prefix("${") {
+                let Some(len) = reference.find('}') else {
+                    break;
+                };
+                out.push_str(&self.lookup(referrer, &reference[..len])?);
+                rest = &reference[len + 1..];
+            } else {
+                out.push('$');
+                rest = &rest[1..];
+            }
+        }
+        out.push_str(rest);
+
+        Ok(Cow::from(out))
+    }
+
+    fn lookup(&mut self, referrer: &str, name: &'src str) -> Result<Cow<'_, str>> {
+        if self.decls.contains_key(name) {
+            self.resolve(name)?;
+            return Ok(Cow::from(self.vars[name].as_ref()));
+        }
+        if let Some(value) = self.env_vars.get(name) {
+            return Ok(Cow::from(*value));
+        }
+        if let Some(value) = self.environment(name) {
+            return Ok(Cow::from(value));
+        }
+        Err(FriggenError::UndefinedVariable {
+            referrer: referrer.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::error::FriggenError;
+    use crate::parser::parse_friggenfile;
+    use crate::vars::resolve_vars;
+
+    #[test]
+    fn test_interpolation() {
+        let ast = parse_friggenfile(
+            r#"
+image = ${registry}/app:${version}
+registry = "example.com"
+version = 1.2.3
+literal = '${registry}'
+"#,
+        )
+        .unwrap();
+
+        let vars = resolve_vars(&[&ast], "bash", None, &HashMap::new(), &HashMap::new()).unwrap();
+        assert_eq!(vars["image"], "example.com/app:1.2.3");
+        assert_eq!(vars["literal"], "${registry}");
+    }
+
+    #[test]
+    fn test_escapes() {
+        let ast = parse_friggenfile(
+            r#"
+name = friggen
+quoted = "say \"${name}\""
+lines = "a\tb\nc"
+dollar = "\${name} costs \$5"
+backslash = "C:\\temp\x"
+plain = a\tb
+"#,
+        )
+        .unwrap();
+
+        let vars = resolve_vars(&[&ast], "bash", None, &HashMap::new(), &HashMap::new()).unwrap();
+        assert_eq!(vars["quoted"], "say \"friggen\"");
+        assert_eq!(vars["lines"], "a\tb\nc");
+        assert_eq!(vars["dollar"], "${name} costs $5");
+        assert_eq!(vars["backslash"], "C:\\temp\\x");
+        assert_eq!(vars["plain"], "a\\tb");
+    }
+
+    #[test]
+    fn test_interpolation_env_fallback() {
+        let ast = parse_friggenfile("greeting = hi ${who}\n").unwrap();
+        let env_vars = HashMap::from([("who", "there")]);
+
+        let vars = resolve_vars(&[&ast], "bash", None, &env_vars, &HashMap::new()).unwrap();
+        assert_eq!(vars["greeting"], "hi there");
+    }
+
+    #[test]
+    fn test_precedence() {
+        let ast = parse_friggenfile(
+            r#"
+cli = friggenfile
+assigned = friggenfile
+defaulted ?= friggenfile
+PATH ?= friggenfile
+"#,
+        )
+        .unwrap();
+        let env_vars = HashMap::from([("cli", "cli")]);
+
+        let vars = resolve_vars(&[&ast], "bash", None, &env_vars, &HashMap::new()).unwrap();
+        assert_eq!(vars["cli"], "cli");
+        assert_eq!(vars["assigned"], "friggenfile");
+        assert_eq!(vars["defaulted"], "friggenfile");
+        assert_eq!(vars["PATH"], std::env::var("PATH").unwrap());
+    }
+
+    #[test]
+    fn test_env_file_vars() {
+        let ast = parse_friggenfile(
+            r#"
+assigned = friggenfile
+defaulted ?= friggenfile
+PATH ?= friggenfile
+greeting = hi ${who}
+listed = $(echo "$who")
+"#,
+        )
+        .unwrap();
+        let env_file_vars = HashMap::from([
+            ("assigned", "env file"),
+            ("defaulted", "env file"),
+            ("who", "env file"),
+        ]);
+
+        let vars = resolve_vars(&[&ast], "bash", None, &HashMap::new(), &env_file_vars).unwrap();
+        assert_eq!(vars["assigned"], "friggenfile");
+        assert_eq!(vars["defaulted"], "env file");
+        assert_eq!(vars["PATH"], std::env::var("PATH").unwrap());
+        assert_eq!(vars["greeting"], "hi env file");
+        assert_eq!(vars["listed"], "env file");
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        let ast = parse_friggenfile("foo = ${friggen_nope}\n").unwrap();
+
+        let err =
+            resolve_vars(&[&ast], "bash", None, &HashMap::new(), &HashMap::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            FriggenError::UndefinedVariable { referrer, name }
+                if referrer == "foo" && name == "friggen_nope"
+        ));
+    }
+
+    #[test]
+    fn test_cyclic_reference() {
+        let ast = parse_friggenfile("a = ${b}\nb = ${c}\nc = ${a}\n").unwrap();
+
+        let err =
+            resolve_vars(&[&ast], "bash", None, &HashMap::new(), &HashMap::new()).unwrap_err();
+        assert!(matches!(
+            err,
+            FriggenError::CyclicVariableReference(names) if names == ["a", "b", "c", "a"]
+        ));
+    }
+}